@@ -1,42 +1,155 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::ids::{BookId, ChapterId, QidianUrl, VolumeId};
+
 /// 整本书的信息
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BookInfo {
     pub volumes: Vec<BookVolume>,
-    pub id: String,
+    pub id: BookId,
+    /// 书名
+    #[serde(default)]
+    pub title: String,
 }
 
 /// 一本书的一卷
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BookVolume {
     /// 标题
     pub title: String,
     /// 是否为 VIP 卷
     pub is_vip: bool,
     /// id
-    pub id: String,
+    pub id: VolumeId,
     /// 章节列表
     pub chapters: Vec<BookChapter>,
 }
 
 /// 一本书的一章
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BookChapter {
     /// 标题
     pub title: String,
     /// 字数
     pub length: u32,
-    /// "首发时间" (发布日期)
-    pub release_date: String,
+    /// "首发时间" (发布日期), 目录里没写或者看不懂就是 `None`
+    #[serde(default, deserialize_with = "deserialize_release_date")]
+    pub release_date: Option<ReleaseTime>,
     /// id
-    pub id: String,
-    /// url
+    pub id: ChapterId,
+    /// 目录里的原始链接, 见 [`QidianUrl`]
     pub url: String,
+    /// 免费 / VIP / 没订阅, 从目录里的标记来
+    #[serde(default)]
+    pub access: ChapterAccess,
+}
+
+/// 章节能不能读
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChapterAccess {
+    Free,
+    /// VIP 章节, 当前账号能读 (已订阅)
+    Vip,
+    /// VIP 章节, 当前账号没订阅
+    Locked,
+    /// 目录里没有标记
+    #[default]
+    Unknown,
+}
+
+impl fmt::Display for ChapterAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChapterAccess::Free => "免费",
+            ChapterAccess::Vip => "VIP",
+            ChapterAccess::Locked => "未订阅",
+            ChapterAccess::Unknown => "未知",
+        })
+    }
+}
+
+/// 各种章节的数量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessSummary {
+    pub free: usize,
+    pub vip: usize,
+    pub locked: usize,
+    pub unknown: usize,
+}
+
+impl fmt::Display for AccessSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "免费 {} 章, VIP {} 章 (可读 {} 章, 未订阅 {} 章)",
+            self.free,
+            self.vip + self.locked,
+            self.vip,
+            self.locked
+        )?;
+        if self.unknown > 0 {
+            write!(f, ", 不知道能不能读 {} 章", self.unknown)?;
+        }
+        Ok(())
+    }
 }
 
 impl BookInfo {
     pub fn length(&self) -> u32 {
         self.volumes.iter().map(|volume| volume.length()).sum()
     }
+
+    /// 书名, 不知道书名时用 id
+    pub fn display_title(&self) -> &str {
+        if self.title.is_empty() {
+            self.id.as_str()
+        } else {
+            &self.title
+        }
+    }
+
+    /// 只留下 `[since, until)` 之间发布的章节, 没有发布时间的章节保留
+    pub fn retain_released(&mut self, since: Option<ReleaseTime>, until: Option<ReleaseTime>) {
+        for volume in &mut self.volumes {
+            volume
+                .chapters
+                .retain(|chapter| chapter.released_within(since, until));
+        }
+    }
+
+    /// 最新一章的发布时间
+    pub fn latest_release(&self) -> Option<ReleaseTime> {
+        self.volumes
+            .iter()
+            .flat_map(|volume| &volume.chapters)
+            .filter_map(|chapter| chapter.release_date)
+            .max()
+    }
+
+    pub fn access_summary(&self) -> AccessSummary {
+        let mut summary = AccessSummary::default();
+        for chapter in self.volumes.iter().flat_map(|volume| &volume.chapters) {
+            match chapter.access {
+                ChapterAccess::Free => summary.free += 1,
+                ChapterAccess::Vip => summary.vip += 1,
+                ChapterAccess::Locked => summary.locked += 1,
+                ChapterAccess::Unknown => summary.unknown += 1,
+            }
+        }
+        summary
+    }
+
+    /// 当前账号没订阅的章节
+    pub fn locked_chapters(&self) -> impl Iterator<Item = &BookChapter> {
+        self.volumes
+            .iter()
+            .flat_map(|volume| &volume.chapters)
+            .filter(|chapter| chapter.is_locked())
+    }
 }
 
 impl BookVolume {
@@ -46,32 +159,424 @@ impl BookVolume {
 }
 
 impl BookChapter {
-    pub fn new(title: String, length: u32, release_date: String, id: String, url: String) -> Self {
+    pub fn new(
+        title: String,
+        length: u32,
+        release_date: Option<ReleaseTime>,
+        id: ChapterId,
+        url: String,
+    ) -> Self {
         Self {
             title,
             length,
             release_date,
             id,
             url,
+            access: ChapterAccess::Unknown,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.access == ChapterAccess::Locked
+    }
+
+    /// 从目录里的链接建一章, 链接不是章节链接时返回 `None`
+    pub fn new_from_html(
+        href: &str,
+        release_date: Option<ReleaseTime>,
+        title: String,
+        length: u32,
+    ) -> Option<Self> {
+        // //www.qidian.com/chapter/1036741406/748679604/
+        let chapter_id = QidianUrl::parse(href)?.chapter_id()?.clone();
+        Some(Self::new(
+            title,
+            length,
+            release_date,
+            chapter_id,
+            href.to_string(),
+        ))
+    }
+
+    pub fn qidian_url(&self) -> Option<QidianUrl> {
+        QidianUrl::parse(&self.url)
+    }
+
+    /// 在目录页里找这一章链接的 css 选择器
+    pub fn link_selector(&self) -> String {
+        match self.qidian_url() {
+            Some(url) => url.link_selector(),
+            None => format!("a[href*='/{}']", self.id),
         }
     }
 
-    pub fn new_from_html(href: &str, release_date: String, title: String, length: u32) -> Self {
-        let (chapter_id, url) = {
-            // //www.qidian.com/chapter/1036741406/748679604/
-            (
-                href.split('/').nth(5).unwrap().to_string(),
-                href.to_string(),
+    /// 完整的章节链接
+    pub fn http_url(&self) -> String {
+        self.qidian_url()
+            .map(|url| url.to_url())
+            .unwrap_or_else(|| self.url.clone())
+    }
+
+    /// 是否在 `[since, until)` 之间发布, 不知道发布时间的算在里面
+    pub fn released_within(&self, since: Option<ReleaseTime>, until: Option<ReleaseTime>) -> bool {
+        let Some(release) = self.release_date else {
+            return true;
+        };
+        since.is_none_or(|since| release >= since) && until.is_none_or(|until| release < until)
+    }
+}
+
+/// 起点上的时间都是北京时间 (UTC+8, 没有夏令时)
+pub fn beijing() -> FixedOffset {
+    FixedOffset::east_opt(8 * 60 * 60).unwrap()
+}
+
+/// 章节的发布时间, 固定按北京时间解释
+///
+/// 序列化成目录里的格式 `2023-04-03 10:19:10`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReleaseTime(DateTime<FixedOffset>);
+
+impl ReleaseTime {
+    pub const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    pub fn from_naive(naive: NaiveDateTime) -> Self {
+        Self(beijing().from_local_datetime(&naive).unwrap())
+    }
+
+    pub fn from_timestamp(secs: i64) -> Option<Self> {
+        Some(Self(
+            DateTime::from_timestamp(secs, 0)?.with_timezone(&beijing()),
+        ))
+    }
+
+    /// unix 时间戳 (秒)
+    pub fn timestamp(&self) -> i64 {
+        self.0.timestamp()
+    }
+
+    pub fn date_time(&self) -> DateTime<FixedOffset> {
+        self.0
+    }
+}
+
+impl FromStr for ReleaseTime {
+    type Err = std::io::Error;
+    /// `2023-04-03 10:19:10`, `2023-04-03 10:19`, `2023-04-03` (当天零点) 或者带时区的 RFC 3339
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        for format in [Self::FORMAT, "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+                return Ok(Self::from_naive(naive));
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(Self::from_naive(date.and_hms_opt(0, 0, 0).unwrap()));
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self(time.with_timezone(&beijing())));
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Invalid time, expect YYYY-MM-DD[ HH:MM[:SS]]",
+        ))
+    }
+}
+
+impl fmt::Display for ReleaseTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format(Self::FORMAT))
+    }
+}
+
+impl Serialize for ReleaseTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ReleaseTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// 以前的 catalog.json 里发布时间是字符串, 空字符串当作没有
+fn deserialize_release_date<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ReleaseTime>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.trim().is_empty() => s.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+/// 从章节标题里分析出来的序号
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterNumbering {
+    /// 章节序号, 标题里没有就是 `None`
+    pub number: Option<u64>,
+    /// 去掉序号之后的标题
+    pub name: String,
+}
+
+/// 单个汉字数字
+fn chinese_digit(c: char) -> Option<u64> {
+    Some(match c {
+        '零' | '〇' => 0,
+        '一' | '壹' => 1,
+        '二' | '两' | '贰' => 2,
+        '三' | '叁' => 3,
+        '四' | '肆' => 4,
+        '五' | '伍' => 5,
+        '六' | '陆' => 6,
+        '七' | '柒' => 7,
+        '八' | '捌' => 8,
+        '九' | '玖' => 9,
+        _ => return None,
+    })
+}
+
+fn chinese_unit(c: char) -> Option<u64> {
+    Some(match c {
+        '十' | '拾' => 10,
+        '百' | '佰' => 100,
+        '千' | '仟' => 1000,
+        _ => return None,
+    })
+}
+
+/// 全角数字转成半角
+fn ascii_digit(c: char) -> Option<u64> {
+    match c {
+        '0'..='9' => Some(c as u64 - '0' as u64),
+        '０'..='９' => Some(c as u64 - '０' as u64),
+        _ => None,
+    }
+}
+
+/// 解析汉字数字, "一百二十三", "两千零五", "十一", "一二三" (逐位写的)
+pub fn parse_chinese_number(s: &str) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    let has_unit = s.chars().any(|c| chinese_unit(c).is_some() || c == '万');
+    if !has_unit {
+        return s.chars().try_fold(0u64, |acc, c| {
+            acc.checked_mul(10)?.checked_add(chinese_digit(c)?)
+        });
+    }
+
+    let (mut total, mut section, mut digit) = (0u64, 0u64, None::<u64>);
+    // 万以下的单位要一个比一个小, "百百" 和 "十百" 都不对
+    let mut last_unit = None::<u64>;
+    let mut seen_wan = false;
+    for c in s.chars() {
+        if matches!(c, '零' | '〇') {
+            // 零只是占位, "一千零十" 是一千一十
+            if digit.is_some() {
+                return None;
+            }
+        } else if let Some(d) = chinese_digit(c) {
+            if digit.replace(d).is_some() {
+                return None;
+            }
+        } else if let Some(unit) = chinese_unit(c) {
+            if last_unit.is_some_and(|last| unit >= last) {
+                return None;
+            }
+            last_unit = Some(unit);
+            // "十一" 前面没有数字时是一十
+            section += digit.take().unwrap_or(1) * unit;
+        } else if c == '万' && !seen_wan {
+            seen_wan = true;
+            total = (section + digit.take().unwrap_or(0)) * 10_000;
+            section = 0;
+            last_unit = None;
+        } else {
+            return None;
+        }
+    }
+    Some(total + section + digit.unwrap_or(0))
+}
+
+/// 章节序号后面跟的分隔符
+const NUMBER_SEPARATORS: &[char] = &['.', '．', '、', ',', '，', ':', '：', '-', '—', ' ', '　'];
+
+impl ChapterNumbering {
+    /// "1.应杰", "第123章 标题", "第一百二十三章：标题", "１２.标题"
+    pub fn parse(title: &str) -> Self {
+        let title = title.trim();
+        let bare = |rest: &str| {
+            rest.trim_start_matches(NUMBER_SEPARATORS)
+                .trim()
+                .to_string()
+        };
+
+        if let Some(rest) = title.strip_prefix('第') {
+            let end = rest.find(['章', '节', '回', '话']).filter(|end| *end > 0);
+            if let Some(end) = end {
+                let digits = rest[..end].trim();
+                let number = if digits.chars().all(|c| ascii_digit(c).is_some()) {
+                    digits.chars().try_fold(0u64, |acc, c| {
+                        acc.checked_mul(10)?.checked_add(ascii_digit(c)?)
+                    })
+                } else {
+                    parse_chinese_number(digits)
+                };
+                if number.is_some() {
+                    let rest = &rest[end..];
+                    let rest = &rest[rest.chars().next().unwrap().len_utf8()..];
+                    return Self {
+                        number,
+                        name: bare(rest),
+                    };
+                }
+            }
+        }
+
+        let digits_end = title
+            .char_indices()
+            .find(|(_, c)| ascii_digit(*c).is_none())
+            .map_or(title.len(), |(i, _)| i);
+        let rest = &title[digits_end..];
+        if digits_end > 0 && (rest.is_empty() || rest.starts_with(NUMBER_SEPARATORS)) {
+            let number = title[..digits_end].chars().try_fold(0u64, |acc, c| {
+                acc.checked_mul(10)?.checked_add(ascii_digit(c)?)
+            });
+            if number.is_some() {
+                return Self {
+                    number,
+                    name: bare(rest),
+                };
+            }
+        }
+
+        Self {
+            number: None,
+            name: title.to_string(),
+        }
+    }
+}
+
+impl BookChapter {
+    /// 章节序号和去掉序号的标题
+    pub fn numbering(&self) -> ChapterNumbering {
+        ChapterNumbering::parse(&self.title)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_release_time() {
+        let time: ReleaseTime = "2023-04-03 10:19:10".parse().unwrap();
+        assert_eq!(time.to_string(), "2023-04-03 10:19:10");
+        // 北京时间 10 点是 UTC 2 点
+        assert_eq!(time.timestamp(), 1680488350);
+        assert_eq!(ReleaseTime::from_timestamp(1680488350), Some(time));
+        assert_eq!("2023-04-03T02:19:10Z".parse::<ReleaseTime>().unwrap(), time);
+        assert_eq!(
+            "2023-04-03".parse::<ReleaseTime>().unwrap().to_string(),
+            "2023-04-03 00:00:00"
+        );
+        assert!("昨天".parse::<ReleaseTime>().is_err());
+        assert!(time < "2023-04-03 10:19:11".parse().unwrap());
+    }
+
+    #[test]
+    fn test_release_date_serde() {
+        let chapter = BookChapter::new(
+            "1.应杰".to_string(),
+            2136,
+            "2023-04-03 10:19:10".parse().ok(),
+            ChapterId::new("748679604"),
+            String::new(),
+        );
+        let json = serde_json::to_string(&chapter).unwrap();
+        assert!(json.contains(r#""release_date":"2023-04-03 10:19:10""#));
+        let back: BookChapter = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.release_date, chapter.release_date);
+
+        let old = r#"{"title":"t","length":1,"release_date":"","id":"1","url":""}"#;
+        assert_eq!(
+            serde_json::from_str::<BookChapter>(old)
+                .unwrap()
+                .release_date,
+            None
+        );
+    }
+
+    #[test]
+    fn test_released_within() {
+        let chapter = |date: &str| {
+            BookChapter::new(
+                String::new(),
+                0,
+                date.parse().ok(),
+                ChapterId::default(),
+                String::new(),
             )
         };
-        Self::new(title, length, release_date, chapter_id, url)
+        let since = "2023-04-01".parse().ok();
+        let until = "2023-05-01".parse().ok();
+        assert!(chapter("2023-04-01 00:00:00").released_within(since, until));
+        assert!(!chapter("2023-05-01 00:00:00").released_within(since, until));
+        assert!(!chapter("2023-03-31 23:59:59").released_within(since, None));
+        assert!(chapter("").released_within(since, until));
     }
 
-    pub fn a_href_tag(&self) -> String {
-        self.url.clone()
+    #[test]
+    fn test_parse_chinese_number() {
+        let cases = [
+            ("一", Some(1)),
+            ("十", Some(10)),
+            ("十一", Some(11)),
+            ("二十", Some(20)),
+            ("一百零五", Some(105)),
+            ("一百二十三", Some(123)),
+            ("两千零一十", Some(2010)),
+            ("一万零三百", Some(10300)),
+            ("十二万三千四百五十六", Some(123456)),
+            ("〇", Some(0)),
+            ("二〇一四", Some(2014)),
+            ("壹佰", Some(100)),
+            ("一千零十", Some(1010)),
+            ("一千零一十", Some(1010)),
+            ("", None),
+            ("一百x", None),
+            ("百百", None),
+            ("一十一百", None),
+            ("一二百", None),
+            ("一万万", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_chinese_number(input), expected, "{input}");
+        }
     }
 
-    pub fn http_url(&self) -> String {
-        self.url.replace("//", "https://")
+    #[test]
+    fn test_chapter_numbering() {
+        let cases = [
+            ("1.应杰", Some(1), "应杰"),
+            ("72.Boss竟是我自己", Some(72), "Boss竟是我自己"),
+            ("第123章 标题", Some(123), "标题"),
+            ("第一百二十三章：标题", Some(123), "标题"),
+            ("第两百章", Some(200), ""),
+            ("第１２章 全角", Some(12), "全角"),
+            ("１２、全角", Some(12), "全角"),
+            ("3 空格", Some(3), "空格"),
+            ("请假条", None, "请假条"),
+            ("2009年的夏天", None, "2009年的夏天"),
+            ("第一卷", None, "第一卷"),
+        ];
+        for (title, number, name) in cases {
+            let numbering = ChapterNumbering::parse(title);
+            assert_eq!(numbering.number, number, "{title}");
+            assert_eq!(numbering.name, name, "{title}");
+        }
     }
 }
@@ -0,0 +1,223 @@
+//! 不开浏览器解析目录
+//!
+//! 目录可以来自保存下来的书页, 也可以只是 `#allCatalog` 片段, `-` 表示标准输入
+
+use std::{collections::HashSet, fmt};
+
+use crate::{books::BookVolume, fs_util::read_input, ids::VolumeId, parse_page::book_info};
+
+/// 目录里不对劲的地方, 不影响继续下载
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogWarning {
+    /// 一个 `div.catalog-volume` 都没有, 可能是页面改版了
+    NoVolumes,
+    /// 有章节链接不在任何卷里, 这些章节没有解析
+    ChaptersOutsideVolumes { count: usize },
+    /// 有章节列表但是没有卷头
+    MissingVolumeHeader { index: usize, volume_id: VolumeId },
+    /// 卷头里写的章数和解析出来的不一样
+    ChapterCountMismatch {
+        volume_id: VolumeId,
+        volume_title: String,
+        declared: usize,
+        found: usize,
+    },
+    /// 章节信息不全
+    BadChapter {
+        volume_id: VolumeId,
+        title: String,
+        reason: String,
+    },
+    /// 章节序号跳了
+    NumberingGap {
+        volume_id: VolumeId,
+        after: u64,
+        next: u64,
+    },
+    /// 同一卷里序号重复
+    DuplicateNumber {
+        volume_id: VolumeId,
+        number: u64,
+        title: String,
+    },
+    /// 序号比前面的章节小
+    OutOfOrder {
+        volume_id: VolumeId,
+        number: u64,
+        after: u64,
+        title: String,
+    },
+}
+
+impl fmt::Display for CatalogWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogWarning::NoVolumes => write!(f, "没找到任何卷, 页面结构可能变了"),
+            CatalogWarning::ChaptersOutsideVolumes { count } => {
+                write!(f, "有 {count} 个章节链接不在卷里, 没有解析")
+            }
+            CatalogWarning::MissingVolumeHeader { index, volume_id } => {
+                write!(f, "第 {} 卷 ({volume_id}) 没有卷头", index + 1)
+            }
+            CatalogWarning::ChapterCountMismatch {
+                volume_id,
+                volume_title,
+                declared,
+                found,
+            } => write!(
+                f,
+                "{volume_title} ({volume_id}) 写着共 {declared} 章, 实际解析出 {found} 章"
+            ),
+            CatalogWarning::BadChapter {
+                volume_id,
+                title,
+                reason,
+            } => write!(f, "{volume_id} 里的《{title}》: {reason}"),
+            CatalogWarning::NumberingGap {
+                volume_id,
+                after,
+                next,
+            } => write!(f, "{volume_id} 里第 {after} 章之后是第 {next} 章, 中间缺了"),
+            CatalogWarning::DuplicateNumber {
+                volume_id,
+                number,
+                title,
+            } => write!(f, "{volume_id} 里第 {number} 章重复了: 《{title}》"),
+            CatalogWarning::OutOfOrder {
+                volume_id,
+                number,
+                after,
+                title,
+            } => write!(
+                f,
+                "{volume_id} 里《{title}》是第 {number} 章, 却排在第 {after} 章后面"
+            ),
+        }
+    }
+}
+
+/// 检查一卷里的章节序号: 缺号, 重复, 乱序
+///
+/// 标题里没有序号的章节 (请假条之类) 不参与检查
+pub fn check_numbering(volume: &BookVolume) -> Vec<CatalogWarning> {
+    let mut warnings = Vec::new();
+    let mut seen = HashSet::new();
+    let mut last: Option<u64> = None;
+    for chapter in &volume.chapters {
+        let Some(number) = chapter.numbering().number else {
+            continue;
+        };
+        if !seen.insert(number) {
+            warnings.push(CatalogWarning::DuplicateNumber {
+                volume_id: volume.id.clone(),
+                number,
+                title: chapter.title.clone(),
+            });
+            continue;
+        }
+        match last {
+            Some(after) if number < after => warnings.push(CatalogWarning::OutOfOrder {
+                volume_id: volume.id.clone(),
+                number,
+                after,
+                title: chapter.title.clone(),
+            }),
+            Some(after) if number > after + 1 => warnings.push(CatalogWarning::NumberingGap {
+                volume_id: volume.id.clone(),
+                after,
+                next: number,
+            }),
+            _ => {}
+        }
+        // 乱序的章节不往回拉, 免得后面每一章都报缺号
+        last = last.max(Some(number));
+    }
+    warnings
+}
+
+/// `catalog` 子命令: 解析目录, 打印摘要或者 json
+pub fn run(file: &str, json: bool) -> anyhow::Result<()> {
+    let (book, warnings) = book_info::parse_document(&read_input(file)?);
+    for warning in &warnings {
+        eprintln!("警告: {warning}");
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&book)?);
+        return Ok(());
+    }
+
+    println!("书名: {}", book.title);
+    println!("书 id: {}", book.id);
+    println!("书长度: {}", book.length());
+    if let Some(latest) = book.latest_release() {
+        println!("最近更新: {latest}");
+    }
+    println!("{}", book.access_summary());
+    for volume in &book.volumes {
+        println!(
+            "  {} ({}) {} 章 {} 字{}",
+            volume.title,
+            volume.id,
+            volume.chapters.len(),
+            volume.length(),
+            if volume.is_vip { " VIP" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::books::BookChapter;
+
+    fn volume(titles: &[&str]) -> BookVolume {
+        BookVolume {
+            title: "正文卷".to_string(),
+            is_vip: false,
+            id: VolumeId::new("vol1"),
+            chapters: titles
+                .iter()
+                .enumerate()
+                .map(|(i, title)| {
+                    BookChapter::new(
+                        title.to_string(),
+                        0,
+                        None,
+                        i.to_string().into(),
+                        String::new(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_numbering() {
+        let ok = volume(&["1.一", "2.二", "请假条", "第三章 三", "第4章 四"]);
+        assert!(check_numbering(&ok).is_empty());
+
+        let bad = volume(&["1.一", "2.二", "4.四", "3.三", "5.五", "5.五again"]);
+        assert_eq!(
+            check_numbering(&bad),
+            vec![
+                CatalogWarning::NumberingGap {
+                    volume_id: VolumeId::new("vol1"),
+                    after: 2,
+                    next: 4
+                },
+                CatalogWarning::OutOfOrder {
+                    volume_id: VolumeId::new("vol1"),
+                    number: 3,
+                    after: 4,
+                    title: "3.三".to_string()
+                },
+                CatalogWarning::DuplicateNumber {
+                    volume_id: VolumeId::new("vol1"),
+                    number: 5,
+                    title: "5.五again".to_string()
+                },
+            ]
+        );
+    }
+}
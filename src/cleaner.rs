@@ -0,0 +1,446 @@
+//! 导出前的文字整理
+//!
+//! 解析出来的段落按顺序过一遍规则: 去零宽字符, 拆空行, 统一缩进, 统一标点, 去掉网站的广告行,
+//! 最后是配置文件里自己写的正则替换
+
+use std::{path::Path, sync::OnceLock};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    fs_util::read_input,
+    parse_page::chapter_content::{self, ChapterContent},
+    revisions::{DiffLine, diff_paragraphs},
+};
+
+/// 零宽空格, 零宽连接符, BOM, 软连字符
+const ZERO_WIDTH: &[char] = &[
+    '\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}', '\u{00ad}',
+];
+
+/// 盗版站和老版本页面里常见的广告行, 包含这些的整段去掉
+const BOILERPLATE: &[&str] = &[
+    "本章未完，请点击下一页继续阅读",
+    "最新章节请到起点中文网阅读",
+    "起点中文网www.qidian.com欢迎广大书友光临阅读",
+    "手机用户请到m.qidian.com阅读",
+    "请记住本书首发域名",
+    "天才一秒记住本站地址",
+];
+
+/// 一条规则
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// 去掉零宽字符
+    ZeroWidth,
+    /// 段落里的换行拆成几段, 去掉空段
+    BlankLines,
+    /// 去掉段首段尾的全角空格, 不换行空格之类的, 缩进由导出决定
+    Indentation,
+    /// 全角字母数字转半角, 汉字后面的半角标点转全角, 省略号统一成 "……"
+    Punctuation,
+    /// 去掉包含这些文字的段落
+    Boilerplate(Vec<String>),
+    /// 配置文件里的正则替换
+    Replace {
+        name: String,
+        regex: Regex,
+        replacement: String,
+    },
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2ffff}')
+}
+
+fn ellipsis_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\.{3,}|。{3,}|…+").unwrap())
+}
+
+fn normalize_punctuation(text: &str) -> String {
+    let text = ellipsis_re().replace_all(text, "……");
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let prev_cjk = i > 0 && is_cjk(chars[i - 1]);
+        let next_cjk = chars.get(i + 1).is_some_and(|&next| is_cjk(next));
+        let next_digit = chars.get(i + 1).is_some_and(char::is_ascii_digit);
+        let c = match c {
+            // 全角字母数字
+            '０'..='９' | 'Ａ'..='Ｚ' | 'ａ'..='ｚ' => {
+                char::from_u32(c as u32 - 0xfee0).unwrap_or(c)
+            }
+            ',' if prev_cjk => '，',
+            '?' if prev_cjk => '？',
+            '!' if prev_cjk => '！',
+            ';' if prev_cjk => '；',
+            ':' if prev_cjk && !next_digit => '：',
+            '.' if prev_cjk && (next_cjk || i + 1 == chars.len()) => '。',
+            '(' if next_cjk => '（',
+            ')' if prev_cjk => '）',
+            c => c,
+        };
+        out.push(c);
+    }
+    out
+}
+
+impl Rule {
+    /// 配置文件里用的名字
+    pub fn name(&self) -> &str {
+        match self {
+            Rule::ZeroWidth => "zero_width",
+            Rule::BlankLines => "blank_lines",
+            Rule::Indentation => "indentation",
+            Rule::Punctuation => "punctuation",
+            Rule::Boilerplate(_) => "boilerplate",
+            Rule::Replace { name, .. } => name,
+        }
+    }
+
+    pub fn apply(&self, paragraphs: Vec<String>) -> Vec<String> {
+        match self {
+            Rule::ZeroWidth => paragraphs
+                .into_iter()
+                .map(|p| p.replace(ZERO_WIDTH, ""))
+                .collect(),
+            Rule::BlankLines => paragraphs
+                .iter()
+                .flat_map(|p| p.lines())
+                .filter(|line| !line.trim().is_empty())
+                .map(str::to_string)
+                .collect(),
+            Rule::Indentation => paragraphs
+                .into_iter()
+                .map(|p| p.trim().to_string())
+                .collect(),
+            Rule::Punctuation => paragraphs
+                .iter()
+                .map(|p| normalize_punctuation(p))
+                .collect(),
+            Rule::Boilerplate(lines) => paragraphs
+                .into_iter()
+                .filter(|p| !lines.iter().any(|line| p.contains(line.as_str())))
+                .collect(),
+            Rule::Replace {
+                regex, replacement, ..
+            } => paragraphs
+                .iter()
+                .map(|p| regex.replace_all(p, replacement.as_str()).into_owned())
+                .filter(|p| !p.trim().is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// 配置文件 (json)
+///
+/// ```json
+/// {
+///     "disable": ["punctuation"],
+///     "boilerplate": ["本书由某某网整理"],
+///     "replace": [{ "name": "去掉括号里的网址", "pattern": "（www\\.[^）]*）", "replacement": "" }]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CleanConfig {
+    /// 不用的内置规则
+    pub disable: Vec<String>,
+    /// 额外的广告行
+    pub boilerplate: Vec<String>,
+    pub replace: Vec<ReplaceRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplaceRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub pattern: String,
+    #[serde(default)]
+    pub replacement: String,
+}
+
+/// 一条规则在试运行时改了什么
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleChange {
+    pub rule: String,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+/// 一串按顺序执行的规则, 默认什么都不做
+#[derive(Debug, Clone, Default)]
+pub struct Cleaner {
+    pub rules: Vec<Rule>,
+}
+
+impl Cleaner {
+    /// 所有内置规则
+    pub fn builtin() -> Self {
+        Self::from_config(&CleanConfig::default()).unwrap()
+    }
+
+    pub fn from_config(config: &CleanConfig) -> anyhow::Result<Self> {
+        const BUILTIN: &[&str] = &[
+            "zero_width",
+            "blank_lines",
+            "indentation",
+            "punctuation",
+            "boilerplate",
+        ];
+        for name in &config.disable {
+            anyhow::ensure!(BUILTIN.contains(&name.as_str()), "没有叫 {name} 的内置规则");
+        }
+        let enabled = |name: &str| !config.disable.iter().any(|d| d == name);
+
+        let mut rules = Vec::new();
+        if enabled("zero_width") {
+            rules.push(Rule::ZeroWidth);
+        }
+        if enabled("blank_lines") {
+            rules.push(Rule::BlankLines);
+        }
+        if enabled("indentation") {
+            rules.push(Rule::Indentation);
+        }
+        if enabled("punctuation") {
+            rules.push(Rule::Punctuation);
+        }
+        if enabled("boilerplate") {
+            let lines = BOILERPLATE
+                .iter()
+                .map(|line| line.to_string())
+                .chain(config.boilerplate.iter().cloned())
+                .collect();
+            rules.push(Rule::Boilerplate(lines));
+        }
+        for (i, rule) in config.replace.iter().enumerate() {
+            let regex = Regex::new(&rule.pattern)
+                .with_context(|| format!("替换规则 {:?} 的正则不对", rule.pattern))?;
+            rules.push(Rule::Replace {
+                name: rule
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("replace#{}", i + 1)),
+                regex,
+                replacement: rule.replacement.clone(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// 读配置文件, 没给就只用内置规则
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::builtin());
+        };
+        let config: CleanConfig = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("读取整理规则 {path:?} 失败"))?;
+        Self::from_config(&config)
+    }
+
+    pub fn clean_paragraphs(&self, paragraphs: Vec<String>) -> Vec<String> {
+        self.rules
+            .iter()
+            .fold(paragraphs, |paragraphs, rule| rule.apply(paragraphs))
+    }
+
+    /// 整理正文和作者的话
+    pub fn clean(&self, content: &mut ChapterContent) {
+        if self.rules.is_empty() {
+            return;
+        }
+        content.paragraphs = self.clean_paragraphs(std::mem::take(&mut content.paragraphs));
+        for section in &mut content.sections {
+            section.paragraphs = self.clean_paragraphs(std::mem::take(&mut section.paragraphs));
+        }
+        content
+            .sections
+            .retain(|section| !section.paragraphs.is_empty());
+    }
+
+    /// 一条条规则执行, 记下每条改了哪些段落, 没改的规则不记
+    pub fn dry_run(&self, paragraphs: Vec<String>) -> (Vec<String>, Vec<RuleChange>) {
+        let mut changes = Vec::new();
+        let mut current = paragraphs;
+        for rule in &self.rules {
+            let next = rule.apply(current.clone());
+            let mut change = RuleChange {
+                rule: rule.name().to_string(),
+                removed: Vec::new(),
+                added: Vec::new(),
+            };
+            for line in diff_paragraphs(&current, &next) {
+                match line {
+                    DiffLine::Same(_) => {}
+                    DiffLine::Removed(p) => change.removed.push(p.to_string()),
+                    DiffLine::Added(p) => change.added.push(p.to_string()),
+                }
+            }
+            if !change.removed.is_empty() || !change.added.is_empty() {
+                changes.push(change);
+            }
+            current = next;
+        }
+        (current, changes)
+    }
+}
+
+/// `clean` 子命令: 整理一章保存下来的 html, 试运行时显示每条规则改了什么
+pub fn run(file: &str, config: Option<&str>, dry_run: bool) -> anyhow::Result<()> {
+    let cleaner = Cleaner::load(config.map(Path::new))?;
+    let content = chapter_content::parse(&read_input(file)?);
+    let paragraphs: Vec<String> = content.all_paragraphs().cloned().collect();
+    if !dry_run {
+        for paragraph in cleaner.clean_paragraphs(paragraphs) {
+            println!("{paragraph}");
+        }
+        return Ok(());
+    }
+
+    let (_, changes) = cleaner.dry_run(paragraphs);
+    if changes.is_empty() {
+        println!("没有规则改动这一章");
+    }
+    for change in changes {
+        println!("[{}]", change.rule);
+        for p in &change.removed {
+            println!("- {p:?}");
+        }
+        for p in &change.added {
+            println!("+ {p:?}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_zero_width() {
+        assert_eq!(
+            Rule::ZeroWidth.apply(strings(&["应\u{200b}杰\u{feff}", "正常"])),
+            strings(&["应杰", "正常"])
+        );
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        assert_eq!(
+            Rule::BlankLines.apply(strings(&["第一行\n\n\n第二行", "  ", "第三行"])),
+            strings(&["第一行", "第二行", "第三行"])
+        );
+    }
+
+    #[test]
+    fn test_indentation() {
+        assert_eq!(
+            Rule::Indentation.apply(strings(&["　　第一段", "\u{a0}\u{a0}第二段\t", "第三段"])),
+            strings(&["第一段", "第二段", "第三段"])
+        );
+    }
+
+    #[test]
+    fn test_punctuation() {
+        let cases = [
+            ("他说,走吧!", "他说，走吧！"),
+            ("真的?真的.", "真的？真的。"),
+            ("等等...", "等等……"),
+            ("等等。。。还有…", "等等……还有……"),
+            ("（已经是全角）", "（已经是全角）"),
+            ("(括号里)", "（括号里）"),
+            ("ＡＩ下了１９手", "AI下了19手"),
+            // 英文和数字里的标点不动
+            ("version 1.5, ok?", "version 1.5, ok?"),
+            ("时间10:30", "时间10:30"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                Rule::Punctuation.apply(strings(&[input])),
+                strings(&[expected]),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_boilerplate() {
+        let rule = Rule::Boilerplate(strings(&["请记住本书首发域名"]));
+        assert_eq!(
+            rule.apply(strings(&["正文", "请记住本书首发域名：xxx", "正文二"])),
+            strings(&["正文", "正文二"])
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config: CleanConfig = serde_json::from_str(
+            r#"{
+                "disable": ["punctuation"],
+                "boilerplate": ["本书由某某网整理"],
+                "replace": [
+                    { "name": "网址", "pattern": "（www\\.[^）]*）", "replacement": "" },
+                    { "pattern": "阿尔法狗", "replacement": "AlphaGo" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let cleaner = Cleaner::from_config(&config).unwrap();
+        let names: Vec<_> = cleaner.rules.iter().map(Rule::name).collect();
+        assert_eq!(
+            names,
+            [
+                "zero_width",
+                "blank_lines",
+                "indentation",
+                "boilerplate",
+                "网址",
+                "replace#2"
+            ]
+        );
+        assert_eq!(
+            cleaner.clean_paragraphs(strings(&[
+                "　　阿尔法狗赢了,（www.example.com）",
+                "本书由某某网整理",
+                "（www.example.com）",
+            ])),
+            strings(&["AlphaGo赢了,"])
+        );
+
+        let bad: CleanConfig = serde_json::from_str(r#"{"disable": ["nope"]}"#).unwrap();
+        assert!(Cleaner::from_config(&bad).is_err());
+        let bad: CleanConfig = serde_json::from_str(r#"{"replace": [{"pattern": "("}]}"#).unwrap();
+        assert!(Cleaner::from_config(&bad).is_err());
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let (cleaned, changes) = Cleaner::builtin().dry_run(strings(&[
+            "　　第一段\u{200b}",
+            "他说,走吧",
+            "本章未完，请点击下一页继续阅读",
+        ]));
+        assert_eq!(cleaned, strings(&["第一段", "他说，走吧"]));
+        let rules: Vec<_> = changes.iter().map(|c| c.rule.as_str()).collect();
+        assert_eq!(
+            rules,
+            ["zero_width", "indentation", "punctuation", "boilerplate"]
+        );
+        assert_eq!(changes[2].removed, strings(&["他说,走吧"]));
+        assert_eq!(changes[2].added, strings(&["他说，走吧"]));
+    }
+}
@@ -0,0 +1,457 @@
+//! cookie 的存储
+//!
+//! 默认格式就是 thirtyfour 的 `Vec<Cookie>` 序列化出来的 json,
+//! 另外支持导入导出浏览器插件常用的 Netscape `cookies.txt`
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::fs_util::write_private;
+
+/// 起点登录态相关的 cookie, 这些过期了就得重新登录
+pub const SESSION_COOKIES: &[&str] = &["ywguid", "ywkey", "ywopenid"];
+
+/// 登录 cookie 剩余时间少于这个值 (秒) 时给出提示
+pub const EXPIRE_WARN_SECS: i64 = 3 * 24 * 60 * 60;
+
+/// 一个 cookie
+///
+/// 字段和 thirtyfour 的 `Cookie` 的 json 形式保持一致, 方便直接读写原来的 cookie.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secure: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_only: Option<bool>,
+    /// 过期时间 (unix 时间戳, 秒), `None` 表示会话 cookie
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same_site: Option<String>,
+}
+
+impl StoredCookie {
+    /// 是否已经过期, 会话 cookie 永远不算过期
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= now)
+    }
+
+    /// 还有多少秒过期
+    pub fn remaining(&self, now: i64) -> Option<i64> {
+        self.expiry.map(|expiry| expiry - now)
+    }
+
+    pub fn is_session_cookie(&self) -> bool {
+        SESSION_COOKIES.contains(&self.name.as_str())
+    }
+}
+
+/// cookie 文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CookieFormat {
+    /// thirtyfour 的 json
+    #[default]
+    Json,
+    /// Netscape cookies.txt
+    Netscape,
+}
+
+impl FromStr for CookieFormat {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(CookieFormat::Json),
+            "netscape" | "txt" => Ok(CookieFormat::Netscape),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid cookie format",
+            )),
+        }
+    }
+}
+
+impl CookieFormat {
+    /// 根据扩展名猜格式, `.txt` 当作 Netscape, 其他都当 json
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("txt") => CookieFormat::Netscape,
+            _ => CookieFormat::Json,
+        }
+    }
+
+    /// 根据内容猜格式
+    pub fn detect(content: &str) -> Self {
+        match content.trim_start().chars().next() {
+            Some('[') | Some('{') => CookieFormat::Json,
+            _ => CookieFormat::Netscape,
+        }
+    }
+
+    pub fn parse(self, content: &str) -> anyhow::Result<Vec<StoredCookie>> {
+        match self {
+            CookieFormat::Json => parse_json(content),
+            CookieFormat::Netscape => parse_netscape(content),
+        }
+    }
+
+    pub fn render(self, cookies: &[StoredCookie]) -> anyhow::Result<String> {
+        match self {
+            CookieFormat::Json => Ok(serde_json::to_string_pretty(cookies)?),
+            CookieFormat::Netscape => Ok(to_netscape(cookies)),
+        }
+    }
+}
+
+pub fn parse_json(content: &str) -> anyhow::Result<Vec<StoredCookie>> {
+    Ok(serde_json::from_str(content)?)
+}
+
+/// 解析 Netscape cookies.txt
+///
+/// 每行 7 列, tab 分隔: domain, 是否包含子域名, path, secure, expiry, name, value
+///
+/// `#HttpOnly_` 开头的行表示 http only, 其他 `#` 开头的都是注释
+pub fn parse_netscape(content: &str) -> anyhow::Result<Vec<StoredCookie>> {
+    let mut cookies = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 7 {
+            bail!("cookies.txt 第 {} 行格式错误: {line}", line_no + 1);
+        }
+        let expiry: i64 = fields[4]
+            .trim()
+            .parse()
+            .with_context(|| format!("cookies.txt 第 {} 行过期时间错误", line_no + 1))?;
+        cookies.push(StoredCookie {
+            name: fields[5].to_string(),
+            // value 里理论上不会有 tab, 有也原样拼回去
+            value: fields[6..].join("\t"),
+            path: Some(fields[2].to_string()),
+            domain: Some(fields[0].to_string()),
+            secure: Some(fields[3].eq_ignore_ascii_case("TRUE")),
+            http_only: Some(http_only),
+            expiry: (expiry != 0).then_some(expiry),
+            same_site: None,
+        });
+    }
+    Ok(cookies)
+}
+
+/// 输出成 Netscape cookies.txt
+pub fn to_netscape(cookies: &[StoredCookie]) -> String {
+    let bool_str = |b: bool| if b { "TRUE" } else { "FALSE" };
+    let mut out = String::from("# Netscape HTTP Cookie File\n");
+    for cookie in cookies {
+        let domain = cookie.domain.as_deref().unwrap_or("");
+        if cookie.http_only.unwrap_or(false) {
+            out.push_str("#HttpOnly_");
+        }
+        out.push_str(&format!(
+            "{domain}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            bool_str(domain.starts_with('.')),
+            cookie.path.as_deref().unwrap_or("/"),
+            bool_str(cookie.secure.unwrap_or(false)),
+            cookie.expiry.unwrap_or(0),
+            cookie.name,
+            cookie.value,
+        ));
+    }
+    out
+}
+
+/// 当前 unix 时间戳 (秒)
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// 账号名, 会拼进 cookie 文件名里
+///
+/// 只能有字母, 数字和 `-_.@`, 不能以 `.` 开头, 免得 `/` 或者 `..` 跑出 cookie 所在的目录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountName(String);
+
+impl AccountName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for AccountName {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let valid = !s.is_empty()
+            && !s.starts_with('.')
+            && s.chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'));
+        if !valid {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid account name, expect letters, digits and -_.@",
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl std::fmt::Display for AccountName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 某个账号的 cookie 文件路径
+///
+/// 默认账号就是 `cookie.json` 本身, 其他账号是 `cookie.<账号>.json`
+pub fn profile_path(base: &Path, account: Option<&AccountName>) -> PathBuf {
+    match account {
+        None => base.to_path_buf(),
+        Some(account) => {
+            let stem = base
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("cookie");
+            let file_name = match base.extension().and_then(|s| s.to_str()) {
+                Some(ext) => format!("{stem}.{account}.{ext}"),
+                None => format!("{stem}.{account}"),
+            };
+            base.with_file_name(file_name)
+        }
+    }
+}
+
+/// 一个账号的 cookie 文件
+#[derive(Debug, Clone)]
+pub struct CookieStore {
+    pub path: PathBuf,
+    pub cookies: Vec<StoredCookie>,
+}
+
+impl CookieStore {
+    /// 读取 cookie 文件, 不存在就是空的
+    pub fn load(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let cookies = if path.exists() {
+            let content = std::fs::read_to_string(&path)?;
+            CookieFormat::detect(&content)
+                .parse(&content)
+                .with_context(|| format!("读取 cookie 文件 {path:?} 失败"))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, cookies })
+    }
+
+    pub fn from_config(config: &crate::CliArg) -> anyhow::Result<Self> {
+        Self::load(profile_path(
+            Path::new(&config.cookie_path),
+            config.account.as_ref(),
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    /// 删掉已经过期的 cookie, 返回被删掉的
+    pub fn prune_expired(&mut self, now: i64) -> Vec<StoredCookie> {
+        let (expired, alive) = std::mem::take(&mut self.cookies)
+            .into_iter()
+            .partition(|cookie| cookie.is_expired(now));
+        self.cookies = alive;
+        expired
+    }
+
+    /// 快要过期的登录 cookie
+    pub fn expiring_soon(&self, now: i64, within: i64) -> Vec<&StoredCookie> {
+        self.cookies
+            .iter()
+            .filter(|cookie| cookie.is_session_cookie())
+            .filter(|cookie| cookie.remaining(now).is_some_and(|left| left <= within))
+            .collect()
+    }
+
+    /// 清理过期 cookie 并在终端提示快过期的登录 cookie
+    pub fn check_expiry(&mut self) {
+        let now = now();
+        let expired = self.prune_expired(now);
+        if !expired.is_empty() {
+            info!(count = expired.len(), "丢弃已过期的 cookie");
+        }
+        for cookie in self.expiring_soon(now, EXPIRE_WARN_SECS) {
+            let hours = cookie.remaining(now).unwrap_or_default() / 3600;
+            warn!(cookie = %cookie.name, hours, "登录 cookie 即将过期");
+        }
+    }
+
+    /// 原子地写入 json, 只有自己能读写
+    pub fn save(&self) -> anyhow::Result<()> {
+        let json = CookieFormat::Json.render(&self.cookies)?;
+        write_private(&self.path, json.as_bytes())
+    }
+
+    pub fn to_webdriver(&self) -> anyhow::Result<Vec<thirtyfour::Cookie>> {
+        Ok(serde_json::from_value(serde_json::to_value(
+            &self.cookies,
+        )?)?)
+    }
+
+    pub fn set_from_webdriver(&mut self, cookies: &[thirtyfour::Cookie]) -> anyhow::Result<()> {
+        self.cookies = serde_json::from_value(serde_json::to_value(cookies)?)?;
+        Ok(())
+    }
+}
+
+/// `cookie` 子命令
+pub fn run(config: &crate::CliArg, action: &crate::CookieAction) -> anyhow::Result<()> {
+    match action {
+        crate::CookieAction::Import { file, format } => {
+            let content = std::fs::read_to_string(file)?;
+            let format = format.unwrap_or_else(|| CookieFormat::detect(&content));
+            let mut store = CookieStore::from_config(config)?;
+            store.cookies = format.parse(&content)?;
+            store.check_expiry();
+            store.save()?;
+            println!(
+                "导入了 {} 个 cookie 到 {:?}",
+                store.cookies.len(),
+                store.path
+            );
+        }
+        crate::CookieAction::Export { file, format } => {
+            let store = CookieStore::from_config(config)?;
+            let format = format.unwrap_or_else(|| CookieFormat::from_path(Path::new(file)));
+            write_private(Path::new(file), format.render(&store.cookies)?.as_bytes())?;
+            println!("导出了 {} 个 cookie 到 {file}", store.cookies.len());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cookie(name: &str, expiry: Option<i64>) -> StoredCookie {
+        StoredCookie {
+            name: name.to_string(),
+            value: "v".to_string(),
+            path: Some("/".to_string()),
+            domain: Some(".qidian.com".to_string()),
+            secure: Some(false),
+            http_only: Some(false),
+            expiry,
+            same_site: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_thirtyfour_json() {
+        let json = r#"[
+            {"name":"ywkey","value":"abc","path":"/","domain":".qidian.com",
+             "secure":true,"httpOnly":true,"expiry":1700000000,"sameSite":"Lax"},
+            {"name":"_csrfToken","value":"x"}
+        ]"#;
+        let cookies = parse_json(json).unwrap();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].http_only, Some(true));
+        assert_eq!(cookies[0].expiry, Some(1700000000));
+        assert_eq!(cookies[0].same_site.as_deref(), Some("Lax"));
+        assert_eq!(cookies[1].expiry, None);
+        assert_eq!(CookieFormat::detect(json), CookieFormat::Json);
+    }
+
+    #[test]
+    fn test_parse_netscape() {
+        let txt = "# Netscape HTTP Cookie File\n\
+                   \n\
+                   .qidian.com\tTRUE\t/\tFALSE\t1700000000\tywguid\t123\n\
+                   #HttpOnly_.qidian.com\tTRUE\t/\tTRUE\t0\tywkey\tk=v\n";
+        let cookies = parse_netscape(txt).unwrap();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].name, "ywguid");
+        assert_eq!(cookies[0].expiry, Some(1700000000));
+        assert_eq!(cookies[1].http_only, Some(true));
+        assert_eq!(cookies[1].secure, Some(true));
+        assert_eq!(cookies[1].expiry, None);
+        assert_eq!(cookies[1].value, "k=v");
+        assert_eq!(CookieFormat::detect(txt), CookieFormat::Netscape);
+
+        assert!(parse_netscape("bad line\n").is_err());
+    }
+
+    #[test]
+    fn test_netscape_round_trip() {
+        let mut http_only = cookie("ywkey", None);
+        http_only.http_only = Some(true);
+        let cookies = vec![cookie("ywguid", Some(1700000000)), http_only];
+        let txt = to_netscape(&cookies);
+        assert_eq!(parse_netscape(&txt).unwrap(), cookies);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let now = 1_000_000;
+        let mut store = CookieStore {
+            path: PathBuf::from("cookie.json"),
+            cookies: vec![
+                cookie("ywkey", Some(now - 1)),
+                cookie("ywguid", Some(now + 60)),
+                cookie("ywopenid", Some(now + EXPIRE_WARN_SECS * 2)),
+                cookie("other", Some(now + 60)),
+                cookie("session", None),
+            ],
+        };
+        let expired = store.prune_expired(now);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].name, "ywkey");
+        assert_eq!(store.cookies.len(), 4);
+
+        let soon: Vec<_> = store
+            .expiring_soon(now, EXPIRE_WARN_SECS)
+            .into_iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(soon, vec!["ywguid"]);
+    }
+
+    #[test]
+    fn test_profile_path() {
+        let base = Path::new("data/cookie.json");
+        assert_eq!(profile_path(base, None), PathBuf::from("data/cookie.json"));
+        let alt: AccountName = "alt".parse().unwrap();
+        assert_eq!(
+            profile_path(base, Some(&alt)),
+            PathBuf::from("data/cookie.alt.json")
+        );
+        assert!("me@qidian.com".parse::<AccountName>().is_ok());
+        for name in ["", "../alt", "a/b", "a\\b", "..", ".alt"] {
+            assert!(name.parse::<AccountName>().is_err(), "{name}");
+        }
+    }
+}
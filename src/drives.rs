@@ -1,37 +1,122 @@
-use rand::Rng;
-use serde_json::from_str;
 use std::{
     path::{Path, PathBuf},
-    str::FromStr,
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use thirtyfour::{
-    By, ChromiumLikeCapabilities, Cookie, DesiredCapabilities, Key, WebDriver, WebElement,
+    By, ChromiumLikeCapabilities, Cookie, DesiredCapabilities, Key, WebDriver,
     prelude::ElementWaitable,
 };
 
-use crate::CliArg;
+use base64::Engine as _;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{
+    CliArg,
+    books::BookChapter,
+    cookies::CookieStore,
+    fs_util::{DirLock, write_atomic},
+    ids::QidianUrl,
+    images::ImageIndex,
+    library::CATALOG_FILE,
+    manifest::Manifest,
+    parse_page::{
+        chapter_content,
+        reader_page::{self, PageKind},
+        site_header::{self, LoginState},
+    },
+    progress::{self, DownloadProgress},
+    rate_limit::{RateLimitConfig, RateLimiter},
+    report::{RunEventKind, RunReport},
+    revisions,
+    shutdown::{Interrupted, Shutdown},
+    storage::{ChapterKey, open_sink},
+};
 
 #[derive(Debug, Clone)]
 pub struct Driver {
     pub driver: WebDriver,
     pub cfg: CliArg,
+    /// 所有页面跳转共用的频率控制
+    pub limiter: Arc<RateLimiter>,
+    /// Ctrl+C 的取消信号
+    pub shutdown: Shutdown,
 }
 
 const ROOT_QIDIAN: &str = "https://www.qidian.com";
 
+/// 在页面里带着 cookie 读一张图片, 回调里给 base64 或者错误信息
+const FETCH_IMAGE_SCRIPT: &str = r#"
+const done = arguments[arguments.length - 1];
+const url = new URL(arguments[0], location.href);
+// 图床回的多是 `Access-Control-Allow-Origin: *`, 带着 cookie 去请求会被浏览器拦下
+const credentials = url.origin === location.origin ? "include" : "omit";
+fetch(url, { credentials })
+    .then((r) => (r.ok ? r.arrayBuffer() : Promise.reject("HTTP " + r.status)))
+    .then((buffer) => {
+        const bytes = new Uint8Array(buffer);
+        let binary = "";
+        for (let i = 0; i < bytes.length; i += 0x8000) {
+            binary += String.fromCharCode.apply(null, bytes.subarray(i, i + 0x8000));
+        }
+        done({ data: btoa(binary) });
+    })
+    .catch((e) => done({ error: String(e) }));
+"#;
+
+/// 读一章的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Fetched {
+    /// 完整的正文 html
+    Content(String),
+    /// 没订阅, 只有试读部分
+    Locked,
+    /// 页面用不了
+    Failed,
+}
+
+/// 把命令行里的浏览器相关选项写进 capabilities
+///
+/// edge 和 chrome 都是 chromium 内核, 参数是通用的
+fn apply_browser_args<C: ChromiumLikeCapabilities>(
+    cap: &mut C,
+    config: &CliArg,
+) -> anyhow::Result<()> {
+    cap.add_arg("--disable-blink-features=AutomationControlled")?;
+    if config.headless {
+        cap.add_arg("--headless=new")?;
+        // 无头模式下没有 GPU 也能跑
+        cap.add_arg("--disable-gpu")?;
+    }
+    if let Some(size) = config.window_size {
+        cap.add_arg(&format!("--window-size={},{}", size.width, size.height))?;
+    }
+    if let Some(profile_dir) = &config.profile_dir {
+        // 浏览器要求绝对路径, 相对路径会被当成相对于浏览器自己的工作目录
+        let profile_dir = std::path::absolute(profile_dir)?;
+        if !profile_dir.exists() {
+            std::fs::create_dir_all(&profile_dir)?;
+        }
+        cap.add_arg(&format!("--user-data-dir={}", profile_dir.display()))?;
+    }
+    for arg in &config.browser_args {
+        cap.add_arg(arg)?;
+    }
+    Ok(())
+}
+
 impl Driver {
     pub async fn new_from_cli(config: CliArg) -> anyhow::Result<Self> {
         let driver = match config.driver_type {
             crate::DriverType::Edge => {
                 let mut cap = DesiredCapabilities::edge();
-                cap.add_arg("--disable-blink-features=AutomationControlled")?;
+                apply_browser_args(&mut cap, &config)?;
                 WebDriver::new(&config.driver_url, cap).await?
             }
             crate::DriverType::Chrome => {
                 let mut cap = DesiredCapabilities::chrome();
-                cap.add_arg("--disable-blink-features=AutomationControlled")?;
+                apply_browser_args(&mut cap, &config)?;
                 WebDriver::new(&config.driver_url, cap).await?
             } // crate::DriverType::Firefox => {
               //     // let mut cap = DesiredCapabilities::firefox();
@@ -47,36 +132,185 @@ impl Driver {
 
         Ok(Self {
             driver,
+            limiter: Arc::new(RateLimiter::new(RateLimitConfig::from_cli(&config))),
+            shutdown: Shutdown::default(),
             cfg: config,
         })
     }
 
+    /// 打开普通页面 (首页, 书页)
+    pub async fn goto(&self, url: &str) -> anyhow::Result<()> {
+        self.limiter.acquire(false).await;
+        self.driver.goto(url).await?;
+        Ok(())
+    }
+
+    /// 直接打开某一章
+    pub async fn goto_chapter(&self, chapter: &BookChapter) -> anyhow::Result<()> {
+        self.limiter.acquire(true).await;
+        self.driver.goto(chapter.http_url()).await?;
+        Ok(())
+    }
+
+    /// 在阅读页按右键翻到下一章
+    pub async fn next_chapter(&self) -> anyhow::Result<()> {
+        self.limiter.acquire(true).await;
+        self.driver
+            .active_element()
+            .await?
+            .send_keys(Key::Right)
+            .await?;
+        Ok(())
+    }
+
+    /// 检查当前页面的登录状态
+    ///
+    /// 需要在起点的普通页面 (首页, 书页) 上调用
+    pub async fn login_state(&self) -> anyhow::Result<LoginState> {
+        Ok(site_header::login_state(&self.driver.source().await?))
+    }
+
+    /// 等用户在浏览器里登录, 最多等 `--login-wait` 秒
+    pub async fn wait_for_login(&self) -> anyhow::Result<LoginState> {
+        let wait = Duration::from_secs(self.cfg.login_wait);
+        progress::println(format!("等待用户登录(等你 {} 秒)", wait.as_secs()));
+        let start = Instant::now();
+        loop {
+            let state = self.login_state().await?;
+            if state.is_logged_in() {
+                return Ok(state);
+            }
+            if start.elapsed() >= wait {
+                anyhow::bail!("等待登录超时 ({} 秒)", wait.as_secs());
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// 在起点首页上确认已经登录, 没登录就点登录按钮等用户登录
+    pub async fn ensure_login(&self) -> anyhow::Result<LoginState> {
+        let state = match self.login_state().await? {
+            LoginState::LoggedOut => {
+                progress::println("点击登录!");
+                self.driver.find(By::Id("login-btn")).await?.click().await?;
+                self.wait_for_login().await?
+            }
+            state => state,
+        };
+        match &state {
+            LoginState::LoggedIn {
+                nickname: Some(nickname),
+            } => progress::println(format!("已登录账号: {nickname}")),
+            LoginState::LoggedIn { nickname: None } => progress::println("已登录"),
+            LoginState::LoggedOut => {}
+            LoginState::Unknown => warn!("无法判断登录状态, 先继续"),
+        }
+        Ok(state)
+    }
+
     pub async fn get_cookie(&self) -> anyhow::Result<Vec<Cookie>> {
+        self.limiter.acquire(false).await;
         self.driver.refresh().await?;
-        // 检测是否需要登录 (寻找 login-btn)
-        let login_btn = self.driver.find(By::Id("login-btn")).await;
-        match login_btn {
-            Ok(login_btn) => {
-                if login_btn.is_displayed().await? {
-                    println!("点击登录!");
-                    login_btn.click().await?;
-                    // 等待登录完成
-                    println!("等待用户登录(等你两分钟)");
-                    // 等你两分钟
-                    login_btn
-                        .wait_until()
-                        .wait(Duration::from_secs(120), Duration::from_secs(1))
-                        .not_displayed()
-                        .await?;
-                }
+        self.ensure_login().await?;
+
+        Ok(self.driver.get_all_cookies().await?)
+    }
+
+    /// 判断当前页面是正常阅读页, 验证页, 登录页还是别的什么
+    pub async fn classify_page(&self) -> anyhow::Result<PageKind> {
+        let url = self.driver.current_url().await?.to_string();
+        let html = self.driver.source().await?;
+        Ok(reader_page::classify(&url, &html))
+    }
+
+    /// 遇到人机验证: 在终端提醒用户, 等他在浏览器里手动过验证
+    pub async fn wait_for_challenge(&self, chapter: &BookChapter) -> anyhow::Result<()> {
+        if self.cfg.headless {
+            anyhow::bail!(
+                "在《{}》遇到人机验证, 无头模式下没法手动验证",
+                chapter.title
+            );
+        }
+        let wait = Duration::from_secs(self.cfg.challenge_wait);
+        warn!(chapter_id = %chapter.id, "遇到人机验证");
+        // \x07 让终端响一声
+        progress::println(format!(
+            "\x07!!!!! 在《{}》遇到人机验证, 请在浏览器里手动完成验证 (等你 {} 秒) !!!!!",
+            chapter.title,
+            wait.as_secs()
+        ));
+        let start = Instant::now();
+        while self.classify_page().await? == PageKind::Challenge {
+            if start.elapsed() >= wait {
+                anyhow::bail!("等待人机验证超时 ({} 秒)", wait.as_secs());
             }
-            Err(_) => {
-                // 看来是不需要登录
-                println!("似乎不需要登录");
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+        info!(chapter_id = %chapter.id, elapsed_secs = start.elapsed().as_secs(), "人机验证通过");
+        progress::println("验证通过, 继续下载");
+        if self.classify_page().await? != PageKind::Reader {
+            // 验证完不一定会跳回原来的章节
+            self.goto_chapter(chapter).await?;
+        }
+        Ok(())
+    }
+
+    /// 确保当前停在 `chapter` 的正常阅读页上
+    ///
+    /// 返回 [`PageKind::Reader`], [`PageKind::Unpurchased`] 或者 [`PageKind::Error`]
+    async fn settle_page(
+        &self,
+        chapter: &BookChapter,
+        report: &mut RunReport,
+    ) -> anyhow::Result<PageKind> {
+        let mut retried = false;
+        loop {
+            match self.classify_page().await? {
+                PageKind::Reader => return Ok(PageKind::Reader),
+                PageKind::Challenge => {
+                    report.push(RunEventKind::Challenge, Some(&chapter.id), "遇到人机验证");
+                    self.wait_for_challenge(chapter).await?;
+                }
+                PageKind::LoginWall => {
+                    report.push(RunEventKind::LoginWall, Some(&chapter.id), "登录失效");
+                    self.recover_session(chapter).await?;
+                }
+                PageKind::Unpurchased => {
+                    report.push(RunEventKind::Unpurchased, Some(&chapter.id), "章节未订阅");
+                    return Ok(PageKind::Unpurchased);
+                }
+                PageKind::Error if !retried => {
+                    warn!(chapter_id = %chapter.id, "页面出错, 重新打开一次");
+                    retried = true;
+                    self.goto_chapter(chapter).await?;
+                }
+                PageKind::Error => {
+                    report.push(RunEventKind::PageError, Some(&chapter.id), "页面出错, 跳过");
+                    error!(chapter_id = %chapter.id, title = %chapter.title, "页面还是出错, 跳过");
+                    return Ok(PageKind::Error);
+                }
             }
         }
+    }
 
-        Ok(self.driver.get_all_cookies().await?)
+    /// 下载中途登录失效: 回首页等用户重新登录, 保存 cookie, 再回到 `chapter`
+    pub async fn recover_session(&self, chapter: &BookChapter) -> anyhow::Result<()> {
+        warn!(chapter_id = %chapter.id, "登录状态失效");
+        progress::println(format!(
+            "登录状态失效了, 暂停下载 (停在《{}》)",
+            chapter.title
+        ));
+        self.goto(ROOT_QIDIAN).await?;
+        if !self.ensure_login().await?.is_logged_in() {
+            // 状态判断不出来的时候, 让用户自己去登录
+            self.wait_for_login().await?;
+        }
+        let mut store = CookieStore::from_config(&self.cfg)?;
+        store.set_from_webdriver(&self.driver.get_all_cookies().await?)?;
+        store.save()?;
+        progress::println("重新登录成功, 继续下载");
+        self.goto_chapter(chapter).await?;
+        Ok(())
     }
 
     /// 检查并更新 cookie
@@ -89,20 +323,18 @@ impl Driver {
         // let new_page = pages.last().unwrap();
         // self.driver.switch_to_window(new_page.clone()).await?;
 
-        self.driver.goto(ROOT_QIDIAN).await?;
-        let cookie_path = Path::new(&self.cfg.cookie_path);
-        if cookie_path.exists() {
-            let str = std::fs::read_to_string(cookie_path)?;
-            let cookies: Vec<Cookie> = from_str(&str)?;
-            for cookie in cookies {
-                self.driver.add_cookie(cookie).await?;
-            }
+        self.goto(ROOT_QIDIAN).await?;
+        let mut store = CookieStore::from_config(&self.cfg)?;
+        store.check_expiry();
+        for cookie in store.to_webdriver()? {
+            self.driver.add_cookie(cookie).await?;
         }
         let cookies = self.get_cookie().await?;
-        println!("正在保存新的 cookie");
-        let json = serde_json::to_string_pretty(&cookies)?;
-        std::fs::write(cookie_path, json)?;
-        println!("保存成功");
+        debug!("正在保存新的 cookie");
+        store.set_from_webdriver(&cookies)?;
+        store.check_expiry();
+        store.save()?;
+        info!(path = %store.path.display(), "cookie 已保存");
         if self.driver.windows().await?.len() != 1 {
             self.driver.close_window().await?;
             self.driver
@@ -116,11 +348,11 @@ impl Driver {
     pub async fn close_pop_window(&self) -> anyhow::Result<()> {
         match self.driver.find(By::Css("#reader .content button")).await {
             Ok(element) => {
-                println!("=====找到按键提示弹窗，点击关闭(祈祷起点没有瞎改页面)=====");
+                debug!("找到按键提示弹窗, 点击关闭");
                 element.click().await?;
             }
             Err(_) => {
-                println!("未找到弹窗元素");
+                trace!("未找到弹窗元素");
             }
         }
         Ok(())
@@ -133,88 +365,321 @@ impl Driver {
         Ok(content)
     }
 
+    /// 读取 `chapter` 的正文 html
+    ///
+    /// `jump` 为 true 时先直接打开这一章, 否则认为已经翻到了这一章
+    async fn fetch_chapter(
+        &self,
+        chapter: &BookChapter,
+        jump: bool,
+        report: &mut RunReport,
+    ) -> anyhow::Result<Fetched> {
+        if jump {
+            // 上一章的页面坏了或者被跳过了, 翻页键不一定好使, 直接打开这一章
+            self.goto_chapter(chapter).await?;
+        }
+        match self.settle_page(chapter, report).await? {
+            PageKind::Reader => {}
+            PageKind::Unpurchased => return Ok(Fetched::Locked),
+            _ => return Ok(Fetched::Failed),
+        }
+        match self.driver.find(By::Tag("main")).await {
+            Ok(main_element) => {
+                debug!(chapter_id = %chapter.id, title = %chapter.title, "正在阅读");
+                let html = main_element.inner_html().await?;
+                if reader_page::is_truncated(&html, chapter.length) {
+                    report.push(RunEventKind::Unpurchased, Some(&chapter.id), "只有试读部分");
+                    return Ok(Fetched::Locked);
+                }
+                Ok(Fetched::Content(html))
+            }
+            Err(e) => {
+                error!(chapter_id = %chapter.id, "找不到正文: {e}");
+                Ok(Fetched::Failed)
+            }
+        }
+    }
+
+    /// 用浏览器下载一张图片, 和翻页一样要排队
+    async fn fetch_image(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        self.limiter.acquire(false).await;
+        let ret = self
+            .driver
+            .execute_async(FETCH_IMAGE_SCRIPT, vec![serde_json::json!(url)])
+            .await?;
+        let value = ret.json();
+        match value.get("data").and_then(|data| data.as_str()) {
+            Some(data) => Ok(base64::engine::general_purpose::STANDARD.decode(data)?),
+            None => anyhow::bail!(
+                "{}",
+                value
+                    .get("error")
+                    .and_then(|error| error.as_str())
+                    .unwrap_or("没有返回内容")
+            ),
+        }
+    }
+
+    /// 下载这一章里还没存过的插图, 下载失败只记进报告, 不影响章节
+    async fn save_images(
+        &self,
+        book_dir: &Path,
+        images: &mut ImageIndex,
+        html: &str,
+        chapter: &BookChapter,
+        report: &mut RunReport,
+    ) -> anyhow::Result<()> {
+        let mut saved = false;
+        for image in chapter_content::parse(html).images {
+            if images.contains(book_dir, &image.url) {
+                continue;
+            }
+            match self.fetch_image(&image.url).await {
+                Ok(bytes) => {
+                    let path = images.store(book_dir, &image.url, &bytes)?;
+                    debug!(chapter_id = %chapter.id, url = %image.url, path, "插图已保存");
+                    saved = true;
+                }
+                Err(e) => {
+                    warn!(chapter_id = %chapter.id, url = %image.url, "插图下载失败: {e}");
+                    report.push(
+                        RunEventKind::MissingImage,
+                        Some(&chapter.id),
+                        format!("插图下载失败: {}", image.url),
+                    );
+                }
+            }
+        }
+        if saved {
+            images.save(book_dir)?;
+        }
+        Ok(())
+    }
+
+    /// 结束 webdriver 会话
+    pub async fn quit(&self) -> anyhow::Result<()> {
+        self.driver.clone().quit().await?;
+        Ok(())
+    }
+
     pub async fn download_book(&self, book_url: &str) -> anyhow::Result<Vec<Vec<String>>> {
-        println!("开始下载 url: {}", book_url);
-        self.driver.goto(book_url).await?;
+        info!(book_url, "开始下载");
+        self.goto(book_url).await?;
         let title = self.driver.title().await?;
         let title = title.split("》").next().unwrap().to_string();
         println!("书名: {}", title);
 
         let all = self.driver.find(By::Id("allCatalog")).await?;
         // println!("{}", all.inner_html().await?);
-        let book_info = crate::parse_page::book_info::parse(all.inner_html().await?);
+        let mut book_info = crate::parse_page::book_info::parse(all.inner_html().await?);
+        // https://www.qidian.com/book/1036741406/
+        if let Some(url) = QidianUrl::parse(book_url) {
+            book_info.id = url.book_id().clone();
+        }
+        book_info.title = title.trim_start_matches('《').to_string();
 
         println!("书长度: {}", book_info.length());
-        let first_chapter = book_info.volumes.first().unwrap().chapters.first().unwrap();
+        if let Some(latest) = book_info.latest_release() {
+            println!("最近更新: {latest}");
+        }
+        let access = book_info.access_summary();
+        println!("{access}");
+        if access.locked > 0 && !self.cfg.skip_locked {
+            warn!(
+                locked = access.locked,
+                "有没订阅的章节, 会存成付费提示页, 可以加 --skip-locked 跳过"
+            );
+        }
+        let Some(first_chapter) = book_info
+            .volumes
+            .iter()
+            .flat_map(|volume| &volume.chapters)
+            .next()
+        else {
+            anyhow::bail!("目录里没有解析出章节, 页面结构可能变了, 请看上面的目录警告");
+        };
+        let mut progress = DownloadProgress::new(&book_info);
         let chatper_item = self
             .driver
-            .find(By::Css(format!(
-                "a[href*='{}']",
-                first_chapter.a_href_tag()
-            )))
+            .find(By::Css(first_chapter.link_selector()))
             .await?;
 
-        println!("等1s看看第一章");
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        debug!(chapter_id = %first_chapter.id, "打开第一章");
+        self.limiter.acquire(true).await;
         chatper_item.click().await?;
 
+        let mut report = RunReport::new(book_info.id.clone());
+        let mut jump_to_chapter = false;
+        let mut interrupted = false;
         let mut datas = Vec::with_capacity(book_info.volumes.len());
-        let mut rng = rand::rng();
-        let out_path = PathBuf::from_str("./out")?;
-        if !out_path.exists() {
-            std::fs::create_dir(&out_path)?;
+        let out_path = PathBuf::from(&self.cfg.out_dir).join(book_info.id.as_str());
+        // 锁到函数结束, 别的进程不能同时写这本书
+        let _lock = DirLock::acquire(&out_path)?;
+        // 书库导入的时候要用目录
+        write_atomic(
+            &out_path.join(CATALOG_FILE),
+            serde_json::to_string_pretty(&book_info)?,
+        )?;
+        let manifest_path = out_path.join(Manifest::FILE_NAME);
+        let mut manifest = Manifest::load_for(&manifest_path, &book_info.id)?;
+        let mut sink = open_sink(self.cfg.storage, &out_path)?;
+        let mut images = ImageIndex::load(&out_path)?;
+        let missing = manifest.retain_stored(sink.as_ref())?;
+        if missing > 0 {
+            warn!(missing, "清单里有章节文件不见了, 重新下载");
         }
-        for (count, vol) in book_info.volumes.iter().enumerate() {
+        'volumes: for (count, vol) in book_info.volumes.iter().enumerate() {
             let mut chapter_htmls = Vec::with_capacity(vol.chapters.len());
-            let mut volume_path = out_path.clone();
-            volume_path.push(format!("{count}_{}", vol.title));
-            if !volume_path.exists() {
-                std::fs::create_dir(&volume_path)?;
-            }
+            progress.start_volume(vol);
             for (chp_count, chapter) in vol.chapters.iter().enumerate() {
-                match self.driver.find(By::Tag("main")).await {
-                    Ok(main_element) => {
-                        let html = main_element.inner_html().await?;
-                        // 随机等一段时间 再 关弹窗
-                        println!("正在 阅读 《{}》", chapter.title);
-
-                        let mut chp_path = volume_path.clone();
-                        chp_path.push(format!(
-                            "{chp_count}_{}-{}.html",
-                            chapter.title, chapter.id
-                        ));
-                        print!("保存到 {chp_path:?}");
-                        std::fs::write(chp_path, &html)?;
-                        println!("写完了");
+                if self.shutdown.is_cancelled() {
+                    interrupted = true;
+                    break 'volumes;
+                }
+                if !chapter.released_within(self.cfg.since, self.cfg.until)
+                    || (self.cfg.only_locked && !manifest.is_locked(&chapter.id))
+                {
+                    trace!(chapter_id = %chapter.id, "不在这次要下载的范围内, 跳过");
+                    chapter_htmls.push(String::new());
+                    jump_to_chapter = true;
+                    progress.chapter_skipped(chapter);
+                    continue;
+                }
+                if self.cfg.skip_locked && chapter.is_locked() {
+                    trace!(chapter_id = %chapter.id, "没订阅, 跳过");
+                    report.push(
+                        RunEventKind::Unpurchased,
+                        Some(&chapter.id),
+                        "目录里标着没订阅, 跳过",
+                    );
+                    chapter_htmls.push(String::new());
+                    jump_to_chapter = true;
+                    progress.chapter_skipped(chapter);
+                    continue;
+                }
+                let recheck = manifest.length_changed(chapter);
+                if recheck {
+                    info!(chapter_id = %chapter.id, title = %chapter.title, "目录里的字数变了, 重新检查");
+                } else if manifest.contains(&chapter.id) {
+                    debug!(chapter_id = %chapter.id, "上次已经保存过, 跳过");
+                    chapter_htmls.push(String::new());
+                    jump_to_chapter = true;
+                    progress.chapter_skipped(chapter);
+                    continue;
+                }
+
+                let chapter_start = Instant::now();
+                // 读页面的过程随时可以放弃, 写文件和记清单在 select 外面, 不会只做一半
+                let fetched = tokio::select! {
+                    fetched = self.fetch_chapter(chapter, jump_to_chapter, &mut report) => fetched?,
+                    _ = self.shutdown.cancelled() => {
+                        warn!(chapter_id = %chapter.id, "放弃正在读取的章节");
+                        interrupted = true;
+                        break 'volumes;
+                    }
+                };
+                jump_to_chapter = false;
+                match fetched {
+                    Fetched::Content(html) => {
+                        let hash = revisions::content_hash(&html);
+                        if let Some(number) = revisions::archive_if_changed(
+                            &out_path,
+                            &mut manifest,
+                            sink.as_ref(),
+                            chapter,
+                            &hash,
+                        )? {
+                            info!(chapter_id = %chapter.id, title = %chapter.title, number, "章节内容改过, 旧版本已保留");
+                        }
+                        let key = ChapterKey::new(count, vol, chp_count, chapter);
+                        sink.put(&key, &html)?;
+                        manifest.record(vol, chapter, key.relative_path(), hash);
+                        manifest.save(&manifest_path)?;
+                        self.limiter.chapter_saved().await;
+                        self.save_images(&out_path, &mut images, &html, chapter, &mut report)
+                            .await?;
+                        debug!(
+                            book_id = %book_info.id,
+                            volume_id = %vol.id,
+                            chapter_id = %chapter.id,
+                            elapsed_ms = chapter_start.elapsed().as_millis() as u64,
+                            key = %key.relative_path(),
+                            "章节已保存"
+                        );
 
                         chapter_htmls.push(html);
                     }
-                    Err(e) => {
-                        println!("出毛病啦! {e}")
+                    Fetched::Locked => {
+                        warn!(chapter_id = %chapter.id, title = %chapter.title, "没订阅, 只有试读部分, 不保存");
+                        manifest.record_locked(vol, chapter);
+                        manifest.save(&manifest_path)?;
+                        chapter_htmls.push(String::new());
                     }
-                };
+                    Fetched::Failed => {
+                        // 页面用不了, 下一章直接打开
+                        chapter_htmls.push(String::new());
+                        jump_to_chapter = true;
+                    }
+                }
 
-                std::thread::sleep(Duration::from_millis(50 + rng.random_range(0..50)));
-                self.close_pop_window().await?;
-                // 随机再等一会再看下一章
+                progress.chapter_done(chapter);
+                if jump_to_chapter {
+                    // 下一章直接打开, 不用翻页
+                    continue;
+                }
 
-                std::thread::sleep(Duration::from_millis(rng.random_range(0..50)));
-                self.driver
-                    .active_element()
-                    .await?
-                    .send_keys(Key::Right)
-                    .await?;
+                let flip = async {
+                    self.close_pop_window().await?;
+                    // 等多久由 limiter 决定
+                    self.next_chapter().await
+                };
+                tokio::select! {
+                    flipped = flip => flipped?,
+                    _ = self.shutdown.cancelled() => {
+                        interrupted = true;
+                        break 'volumes;
+                    }
+                }
             }
             datas.push(chapter_htmls);
         }
 
-        for (vol, vol_data) in book_info.volumes.iter().zip(datas.iter()) {
-            for (chatper, chapter_html) in vol.chapters.iter().zip(vol_data.iter()) {
-                let mut path = out_path.clone();
-                path.push(format!("{}-{}", chatper.title, chatper.id));
-                std::fs::write(path, chapter_html)?;
+        progress.finish();
+        manifest.save(&manifest_path)?;
+        report.save(&out_path.join("report.json"))?;
+        if !report.events.is_empty() {
+            println!(
+                "本次遇到 {} 次人机验证, {} 次登录失效, {} 个未订阅章节, {} 个出错页面, {} 张插图没下载成功, 详见 report.json",
+                report.count(RunEventKind::Challenge),
+                report.count(RunEventKind::LoginWall),
+                report.count(RunEventKind::Unpurchased),
+                report.count(RunEventKind::PageError),
+                report.count(RunEventKind::MissingImage),
+            );
+        }
+        if self.cfg.skip_locked && access.locked > 0 {
+            println!("跳过了 {} 个没订阅的章节:", access.locked);
+            for chapter in book_info.locked_chapters() {
+                println!("  {} ({})", chapter.title, chapter.id);
+            }
+        }
+        let locked: Vec<_> = manifest.locked().collect();
+        if !locked.is_empty() {
+            println!(
+                "{} 章没订阅, 没有保存试读部分, 订阅后加 --only-locked 重新下载这些章节:",
+                locked.len()
+            );
+            for (id, entry) in locked {
+                println!("  {} ({id})", entry.title);
             }
         }
+        if interrupted {
+            println!(
+                "下载已中断, 已保存 {} 章, 再次运行会从断点继续",
+                manifest.stored_count()
+            );
+            return Err(Interrupted.into());
+        }
 
         // tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         // self.close_pop_window().await?;
@@ -240,45 +705,54 @@ impl Driver {
 
 pub async fn main(config: CliArg) -> anyhow::Result<()> {
     let driver = Driver::new_from_cli(config).await?;
+    driver.shutdown.listen();
 
-    driver.check_cookie().await?;
+    let result = async {
+        driver.shutdown.run(driver.check_cookie()).await?;
 
-    // tokio::signal::ctrl_c().await?;
-
-    driver
-        .download_book("https://www.qidian.com/book/1042804894/")
-        // .download_book("https://www.qidian.com/book/1036741406/")
-        .await?;
+        driver
+            .download_book("https://www.qidian.com/book/1042804894/")
+            // .download_book("https://www.qidian.com/book/1036741406/")
+            .await?;
+        anyhow::Ok(())
+    }
+    .await;
 
-    tokio::signal::ctrl_c().await?;
+    // 不管成功与否都关掉会话, 免得 webdriver 那边留一堆浏览器
+    if let Err(e) = driver.quit().await {
+        warn!("关闭 webdriver 会话失败: {e}");
+    }
 
-    Ok(())
+    result
 }
 
 pub async fn a_main(config: CliArg) -> anyhow::Result<()> {
     let driver = match config.driver_type {
         crate::DriverType::Edge => {
             let mut cap = DesiredCapabilities::edge();
-            cap.add_arg("--disable-blink-features=AutomationControlled")?;
+            apply_browser_args(&mut cap, &config)?;
             WebDriver::new(&config.driver_url, cap).await?
         }
         crate::DriverType::Chrome => {
             let mut cap = DesiredCapabilities::chrome();
-            cap.add_arg("--disable-blink-features=AutomationControlled")?;
+            apply_browser_args(&mut cap, &config)?;
             WebDriver::new(&config.driver_url, cap).await?
         }
     };
 
+    // 这条路径也得守频率限制
+    let limiter = RateLimiter::new(RateLimitConfig::from_cli(&config));
+
     // 检测是否存在 cookie 文件
-    let cookie_path = Path::new(&config.cookie_path);
+    let mut store = CookieStore::from_config(&config)?;
+    store.check_expiry();
     let mut need_cookie = true;
 
+    limiter.acquire(false).await;
     driver.goto("https://www.qidian.com").await?;
 
-    if cookie_path.exists() {
-        let str = std::fs::read_to_string(cookie_path)?;
-        let cookies: Vec<Cookie> = from_str(&str)?;
-        for cookie in cookies {
+    if !store.is_empty() {
+        for cookie in store.to_webdriver()? {
             driver.add_cookie(cookie).await?;
         }
         need_cookie = false;
@@ -299,6 +773,7 @@ pub async fn a_main(config: CliArg) -> anyhow::Result<()> {
     // driver.goto("https://bot.sannysoft.com/").await?;
     // tokio::signal::ctrl_c().await?;
 
+    limiter.acquire(false).await;
     driver.goto("https://www.qidian.com").await?;
 
     driver
@@ -323,11 +798,13 @@ pub async fn a_main(config: CliArg) -> anyhow::Result<()> {
     if need_cookie {
         let login_btn = driver.find(By::Id("login-btn")).await?;
         login_btn.click().await?;
-        println!("等待用户登录(等你两分钟)");
-        // 等你两分钟
+        println!("等待用户登录(等你 {} 秒)", config.login_wait);
         login_btn
             .wait_until()
-            .wait(Duration::from_secs(120), Duration::from_secs(1))
+            .wait(
+                Duration::from_secs(config.login_wait),
+                Duration::from_secs(1),
+            )
             .not_displayed()
             .await?;
     }
@@ -339,11 +816,12 @@ pub async fn a_main(config: CliArg) -> anyhow::Result<()> {
     {
         // 每次登录后都写入 cookie
         let cookies = driver.get_all_cookies().await?;
-        let str = serde_json::to_string_pretty(&cookies)?;
+        store.set_from_webdriver(&cookies)?;
 
         // 写入
-        std::fs::write(cookie_path, str.as_bytes()).unwrap();
+        store.save()?;
 
+        limiter.acquire(false).await;
         driver
             .goto("https://www.qidian.com/book/1036741406/")
             .await?;
@@ -388,9 +866,7 @@ pub async fn a_main(config: CliArg) -> anyhow::Result<()> {
     //     .await?;
     // driver.goto("https://bot.sannysoft.com/").await?;
 
-    // 等待 ctrl+c
-
-    tokio::signal::ctrl_c().await?;
+    driver.quit().await?;
 
     Ok(())
 }
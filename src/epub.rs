@@ -0,0 +1,334 @@
+//! 导出 EPUB 3
+//!
+//! 每卷一个标题页, 每章一个 xhtml, 正文和 html 导出用同一套渲染. 用到的插图从书目录的
+//! `images/` 里打包进去, 书里的地址和 html 导出一样是 `images/<文件名>`
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    io::{Cursor, Write},
+    path::Path,
+};
+
+use tracing::warn;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    books::BookInfo,
+    export::{self, ExportFormat, ExportOptions, ExportStats, PreparedBook, escape_html},
+    fs_util::write_atomic,
+    images::IMAGE_DIR,
+    storage::ChapterSink,
+};
+
+/// 按扩展名给 OPF 里的 `media-type`
+fn media_type(file: &str) -> &'static str {
+    match file.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("png") => "image/png",
+        Some("jpg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("bmp") => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 一个 xhtml 文件
+fn xhtml(lang: &str, title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{lang}\" xml:lang=\"{lang}\">\n<head><title>{}</title></head>\n<body>\n{body}</body>\n</html>\n",
+        escape_html(title)
+    )
+}
+
+/// 书里的一个文件
+struct Item {
+    id: String,
+    href: String,
+    media_type: &'static str,
+    /// 放进 spine 里按顺序读
+    in_spine: bool,
+}
+
+/// 目录 (nav.xhtml) 里的一卷: (标题, 卷页, [(章节标题, 章节页)])
+type NavVolume = (String, String, Vec<(String, String)>);
+
+fn render_nav(book: &PreparedBook, nav: &[NavVolume], appendix: Option<&str>) -> String {
+    let mut body = String::from("<nav epub:type=\"toc\" id=\"toc\">\n<ol>\n");
+    for (title, href, chapters) in nav {
+        let _ = write!(body, "<li><a href=\"{href}\">{}</a>", escape_html(title));
+        if !chapters.is_empty() {
+            body.push_str("\n<ol>\n");
+            for (title, href) in chapters {
+                let _ = writeln!(
+                    body,
+                    "<li><a href=\"{href}\">{}</a></li>",
+                    escape_html(title)
+                );
+            }
+            body.push_str("</ol>\n");
+        }
+        body.push_str("</li>\n");
+    }
+    if let Some(href) = appendix {
+        let _ = writeln!(
+            body,
+            "<li><a href=\"{href}\">{}</a></li>",
+            escape_html(&book.appendix_title)
+        );
+    }
+    body.push_str("</ol>\n</nav>\n");
+    xhtml(book.lang, &book.title, &body)
+}
+
+fn render_opf(book: &PreparedBook, book_id: &str, items: &[Item]) -> String {
+    let modified = chrono::DateTime::from_timestamp(crate::cookies::now(), 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%SZ");
+    let mut opf = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{0}\">\n<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:identifier id=\"book-id\">urn:qidian:{1}</dc:identifier>\n<dc:title>{2}</dc:title>\n<dc:language>{0}</dc:language>\n<meta property=\"dcterms:modified\">{modified}</meta>\n</metadata>\n<manifest>\n<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+        book.lang,
+        escape_html(book_id),
+        escape_html(&book.title)
+    );
+    for item in items {
+        let _ = writeln!(
+            opf,
+            "<item id=\"{}\" href=\"{}\" media-type=\"{}\"/>",
+            item.id, item.href, item.media_type
+        );
+    }
+    opf.push_str("</manifest>\n<spine>\n");
+    for item in items.iter().filter(|item| item.in_spine) {
+        let _ = writeln!(opf, "<itemref idref=\"{}\"/>", item.id);
+    }
+    opf.push_str("</spine>\n</package>\n");
+    opf
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#;
+
+/// 导出到 `path`, 插图从 `book_dir` 里读
+pub fn write_epub(
+    book: &BookInfo,
+    sink: &dyn ChapterSink,
+    options: &ExportOptions,
+    book_dir: &Path,
+    path: &Path,
+) -> anyhow::Result<ExportStats> {
+    let (prepared, mut stats) = export::prepare_book(book, sink, options)?;
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut items = Vec::new();
+    let mut nav = Vec::new();
+    let mut xhtml_item = |files: &mut Vec<(String, Vec<u8>)>, href: String, text: String| {
+        items.push(Item {
+            id: href.trim_end_matches(".xhtml").to_string(),
+            href: href.clone(),
+            media_type: "application/xhtml+xml",
+            in_spine: true,
+        });
+        files.push((href, text.into_bytes()));
+    };
+
+    let mut images = BTreeSet::new();
+    let mut number = 0;
+    for (index, volume) in prepared.volumes.iter().enumerate() {
+        let volume_href = format!("volume-{index}.xhtml");
+        let body = format!("<h2>{}</h2>\n", escape_html(&volume.title));
+        xhtml_item(
+            &mut files,
+            volume_href.clone(),
+            xhtml(prepared.lang, &volume.title, &body),
+        );
+        let mut chapters = Vec::new();
+        for chapter in &volume.chapters {
+            number += 1;
+            let href = format!("chapter-{number}.xhtml");
+            let mut body = String::new();
+            export::render_chapter(
+                &mut body,
+                ExportFormat::Epub,
+                chapter.chapter,
+                &chapter.title,
+                &chapter.content,
+            )?;
+            images.extend(
+                chapter
+                    .content
+                    .images
+                    .iter()
+                    .filter(|image| image.url.starts_with(&format!("{IMAGE_DIR}/")))
+                    .map(|image| image.url.clone()),
+            );
+            xhtml_item(
+                &mut files,
+                href.clone(),
+                xhtml(prepared.lang, &chapter.title, &body),
+            );
+            chapters.push((chapter.title.clone(), href));
+        }
+        nav.push((volume.title.clone(), volume_href, chapters));
+    }
+    let appendix = (!prepared.appendix.is_empty()).then(|| "appendix.xhtml".to_string());
+    if let Some(href) = &appendix {
+        let mut body = String::new();
+        export::render_appendix(
+            &mut body,
+            ExportFormat::Epub,
+            &prepared.appendix_title,
+            &prepared.appendix,
+        )?;
+        xhtml_item(
+            &mut files,
+            href.clone(),
+            xhtml(prepared.lang, &prepared.appendix_title, &body),
+        );
+    }
+
+    for (index, image) in images.iter().enumerate() {
+        match std::fs::read(book_dir.join(image)) {
+            Ok(bytes) => {
+                items.push(Item {
+                    id: format!("image-{index}"),
+                    href: image.clone(),
+                    media_type: media_type(image),
+                    in_spine: false,
+                });
+                files.push((image.clone(), bytes));
+            }
+            Err(e) => {
+                warn!(image, "插图文件读不了, 没有打包: {e}");
+                stats.missing_images.push(image.clone());
+            }
+        }
+    }
+    if !stats.missing_images.is_empty() {
+        warn!(
+            missing = stats.missing_images.len(),
+            "有插图没下载到本地, 导出的书里用的是原来的地址"
+        );
+    }
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    // mimetype 必须是第一个文件, 并且不压缩
+    writer.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    writer.write_all(b"application/epub+zip")?;
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file("META-INF/container.xml", deflated)?;
+    writer.write_all(CONTAINER_XML.as_bytes())?;
+    writer.start_file("OEBPS/content.opf", deflated)?;
+    writer.write_all(render_opf(&prepared, book.id.as_str(), &items).as_bytes())?;
+    writer.start_file("OEBPS/nav.xhtml", deflated)?;
+    writer.write_all(render_nav(&prepared, &nav, appendix.as_deref()).as_bytes())?;
+    for (name, bytes) in files {
+        writer.start_file(format!("OEBPS/{name}"), deflated)?;
+        writer.write_all(&bytes)?;
+    }
+    write_atomic(path, writer.finish()?.into_inner())?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        books::{BookChapter, BookVolume},
+        ids::{BookId, ChapterId, VolumeId},
+        storage::{ChapterKey, SqliteSink},
+    };
+
+    #[test]
+    fn test_write_epub() {
+        let dir = std::env::temp_dir().join(format!("qidian-epub-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let book = BookInfo {
+            id: BookId::new("1036741406"),
+            title: "书".to_string(),
+            volumes: vec![BookVolume {
+                title: "正文卷".to_string(),
+                is_vip: false,
+                id: VolumeId::new("vol1"),
+                chapters: vec![BookChapter::new(
+                    "3.地图".to_string(),
+                    4,
+                    None,
+                    ChapterId::new("3"),
+                    String::new(),
+                )],
+            }],
+        };
+        let mut sink =
+            SqliteSink::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let volume = &book.volumes[0];
+        sink.put(
+            &ChapterKey::new(0, volume, 0, &volume.chapters[0]),
+            r#"<p>第一段 &amp; 地图</p><img src="https://img.qidian.com/map.png" alt="地图"><img src="https://img.qidian.com/gone.png">"#,
+        )
+        .unwrap();
+        let mut options = ExportOptions::default();
+        let png = b"\x89PNG\r\n\x1a\nfake";
+        options
+            .images
+            .store(&dir, "https://img.qidian.com/map.png", png)
+            .unwrap();
+        let image = options
+            .images
+            .local_path("https://img.qidian.com/map.png")
+            .unwrap();
+
+        let (path, stats) =
+            export::export_book(&book, &sink, ExportFormat::Epub, &options, &dir).unwrap();
+        assert_eq!(path.extension().unwrap(), "epub");
+        assert_eq!(stats.chapters, 1);
+        assert_eq!(
+            stats.missing_images,
+            vec!["https://img.qidian.com/gone.png"]
+        );
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        let read = |archive: &mut zip::ZipArchive<std::fs::File>, name: &str| {
+            let mut text = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let chapter = read(&mut archive, "OEBPS/chapter-1.xhtml");
+        assert!(chapter.contains("<p>第一段 &amp; 地图</p>"));
+        assert!(chapter.contains(&format!("<img src=\"{image}\" alt=\"地图\" />")));
+        assert!(chapter.contains("<img src=\"https://img.qidian.com/gone.png\" alt=\"\" />"));
+        let mut bytes = Vec::new();
+        archive
+            .by_name(&format!("OEBPS/{image}"))
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, png);
+
+        let opf = read(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("urn:qidian:1036741406"));
+        assert!(opf.contains(&format!("href=\"{image}\" media-type=\"image/png\"")));
+        assert!(opf.contains("<itemref idref=\"volume-0\"/>\n<itemref idref=\"chapter-1\"/>"));
+        let nav = read(&mut archive, "OEBPS/nav.xhtml");
+        assert!(nav.contains("<li><a href=\"chapter-1.xhtml\">3.地图</a></li>"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
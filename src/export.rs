@@ -0,0 +1,686 @@
+//! 导出成整本的电子书
+//!
+//! 章节从 [`ChapterSink`] 里读, 用 [`chapter_content`] 解析出正文, 按目录顺序拼起来
+
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use tracing::warn;
+
+use crate::{
+    books::{BookChapter, BookInfo},
+    cleaner::Cleaner,
+    epub,
+    fs_util::write_atomic,
+    ids::ChapterId,
+    images::ImageIndex,
+    parse_page::chapter_content::{
+        self, ChapterContent, ChapterKind, ExtraSection, SectionKind, SectionPolicy,
+    },
+    storage::{ChapterSink, sanitize},
+    zhconv::Converter,
+};
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// 纯文本
+    #[default]
+    Txt,
+    Markdown,
+    /// 单个 html 文件
+    Html,
+    /// 每章一个 xhtml, 插图打包在里面
+    Epub,
+}
+
+impl FromStr for ExportFormat {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "txt" | "text" => Ok(ExportFormat::Txt),
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "epub" => Ok(ExportFormat::Epub),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid export format",
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Txt => "txt",
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Epub => "epub",
+        }
+    }
+}
+
+/// 导出选项
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// 解析之后, 渲染之前整理文字
+    pub cleaner: Cleaner,
+    /// 作者的话, 求票这些怎么放
+    pub extras: SectionPolicy,
+    /// 不导出请假条, 上架感言之类的章节
+    pub skip_announcements: bool,
+    /// 简繁转换, 书名, 目录和正文都转
+    pub converter: Option<Converter>,
+    /// 下载好的插图, html, markdown 和 epub 里的图片地址换成本地文件
+    pub images: ImageIndex,
+}
+
+/// 导出结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportStats {
+    pub chapters: usize,
+    /// 目录里有但存储里没有的章节 id
+    pub missing: Vec<ChapterId>,
+    /// 没订阅的章节, 只导出了标题和一句说明
+    pub locked: Vec<ChapterId>,
+    /// 按 [`ExportOptions::skip_announcements`] 跳过的章节
+    pub skipped: Vec<ChapterId>,
+    /// 没下载到本地的插图地址, 导出的书里还是原来的地址
+    pub missing_images: Vec<String>,
+}
+
+/// 没订阅的章节在导出的书里的占位
+const LOCKED_PLACEHOLDER: &str = "（本章未订阅, 没有正文）";
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 导出的一章, 已经整理和转换好了
+pub struct PreparedChapter<'a> {
+    pub chapter: &'a BookChapter,
+    /// 转换过的标题
+    pub title: String,
+    pub content: ChapterContent,
+}
+
+/// 导出的一卷
+pub struct PreparedVolume<'a> {
+    pub title: String,
+    pub chapters: Vec<PreparedChapter<'a>>,
+}
+
+/// 按目录顺序读出来的整本书, 各种格式都从这里渲染
+pub struct PreparedBook<'a> {
+    pub title: String,
+    /// html 的 `lang`
+    pub lang: &'static str,
+    pub volumes: Vec<PreparedVolume<'a>>,
+    /// 附录的标题
+    pub appendix_title: String,
+    /// 挪到附录的部分: (章节标题, 部分)
+    pub appendix: Vec<(String, Vec<ExtraSection>)>,
+}
+
+/// 读出, 整理, 转换要导出的章节, 插图地址换成本地文件
+pub fn prepare_book<'a>(
+    book: &'a BookInfo,
+    sink: &dyn ChapterSink,
+    options: &ExportOptions,
+) -> anyhow::Result<(PreparedBook<'a>, ExportStats)> {
+    let mut stats = ExportStats::default();
+    // 标题和正文在渲染之前转换, 拼出来的标签和地址不能过转换
+    let converted = options
+        .converter
+        .as_ref()
+        .map(|converter| converter.convert_book(book));
+    let shown = converted.as_ref().unwrap_or(book);
+    let convert = |text: &str| match &options.converter {
+        Some(converter) => converter.convert(text),
+        None => text.to_string(),
+    };
+    let mut prepared = PreparedBook {
+        title: shown.display_title().to_string(),
+        lang: options
+            .converter
+            .as_ref()
+            .map_or("zh", |converter| converter.conversion().lang()),
+        volumes: Vec::new(),
+        appendix_title: convert("附录"),
+        appendix: Vec::new(),
+    };
+
+    for (volume, shown_volume) in book.volumes.iter().zip(&shown.volumes) {
+        let mut chapters = Vec::new();
+        for (chapter, shown_chapter) in volume.chapters.iter().zip(&shown_volume.chapters) {
+            if chapter.is_locked() {
+                chapters.push(PreparedChapter {
+                    chapter,
+                    title: shown_chapter.title.clone(),
+                    content: ChapterContent {
+                        paragraphs: vec![convert(LOCKED_PLACEHOLDER)],
+                        ..Default::default()
+                    },
+                });
+                stats.locked.push(chapter.id.clone());
+                continue;
+            }
+            let Some(html) = sink.get(&chapter.id)? else {
+                stats.missing.push(chapter.id.clone());
+                continue;
+            };
+            let mut content = chapter_content::parse(&html);
+            options.cleaner.clean(&mut content);
+            if options.skip_announcements
+                && chapter_content::classify_chapter(&chapter.title, &content)
+                    == ChapterKind::Announcement
+            {
+                stats.skipped.push(chapter.id.clone());
+                continue;
+            }
+            if let Some(converter) = &options.converter {
+                converter.convert_content(&mut content);
+            }
+            let title = content
+                .title
+                .clone()
+                .unwrap_or_else(|| shown_chapter.title.clone());
+            match options.extras {
+                SectionPolicy::Keep => {}
+                SectionPolicy::Appendix => {
+                    let sections = std::mem::take(&mut content.sections);
+                    if !sections.is_empty() {
+                        prepared.appendix.push((title.clone(), sections));
+                    }
+                }
+                SectionPolicy::Drop => content.sections.clear(),
+            }
+            for image in &mut content.images {
+                match options.images.local_path(&image.url) {
+                    Some(path) => image.url = path,
+                    None => stats.missing_images.push(image.url.clone()),
+                }
+            }
+            chapters.push(PreparedChapter {
+                chapter,
+                title,
+                content,
+            });
+            stats.chapters += 1;
+        }
+        prepared.volumes.push(PreparedVolume {
+            title: shown_volume.title.clone(),
+            chapters,
+        });
+    }
+
+    if !stats.missing.is_empty() {
+        warn!(
+            missing = stats.missing.len(),
+            "有章节还没下载, 导出的书不完整"
+        );
+    }
+    if !stats.locked.is_empty() {
+        warn!(
+            locked = stats.locked.len(),
+            "有章节没订阅, 导出的书里只有标题"
+        );
+    }
+    Ok((prepared, stats))
+}
+
+/// 把整本书渲染成一个字符串, EPUB 见 [`crate::epub`]
+pub fn render_book(
+    book: &BookInfo,
+    sink: &dyn ChapterSink,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> anyhow::Result<(String, ExportStats)> {
+    let (book, stats) = prepare_book(book, sink, options)?;
+    let mut out = String::new();
+    match format {
+        ExportFormat::Txt => writeln!(out, "{}\n", book.title)?,
+        ExportFormat::Markdown => writeln!(out, "# {}\n", book.title)?,
+        ExportFormat::Html | ExportFormat::Epub => writeln!(
+            out,
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<head><meta charset=\"utf-8\"><title>{1}</title></head>\n<body>\n<h1>{1}</h1>",
+            book.lang,
+            escape_html(&book.title)
+        )?,
+    }
+
+    for volume in &book.volumes {
+        match format {
+            ExportFormat::Txt => writeln!(out, "\n{}\n", volume.title)?,
+            ExportFormat::Markdown => writeln!(out, "\n## {}\n", volume.title)?,
+            ExportFormat::Html | ExportFormat::Epub => {
+                writeln!(out, "<h2>{}</h2>", escape_html(&volume.title))?
+            }
+        }
+        for chapter in &volume.chapters {
+            render_chapter(
+                &mut out,
+                format,
+                chapter.chapter,
+                &chapter.title,
+                &chapter.content,
+            )?;
+        }
+    }
+    if !book.appendix.is_empty() {
+        render_appendix(&mut out, format, &book.appendix_title, &book.appendix)?;
+    }
+
+    if matches!(format, ExportFormat::Html | ExportFormat::Epub) {
+        writeln!(out, "</body>\n</html>")?;
+    }
+    if !stats.missing_images.is_empty() && format != ExportFormat::Txt {
+        warn!(
+            missing = stats.missing_images.len(),
+            "有插图没下载到本地, 导出的书里用的是原来的地址"
+        );
+    }
+    Ok((out, stats))
+}
+
+pub fn render_chapter(
+    out: &mut String,
+    format: ExportFormat,
+    chapter: &BookChapter,
+    title: &str,
+    content: &ChapterContent,
+) -> std::fmt::Result {
+    match format {
+        // 纯文本放不了图片
+        ExportFormat::Txt => {
+            writeln!(out, "{title}\n")?;
+            for paragraph in &content.paragraphs {
+                writeln!(out, "　　{paragraph}")?;
+            }
+            render_sections(out, format, &content.sections)?;
+            writeln!(out)
+        }
+        ExportFormat::Markdown => {
+            writeln!(out, "### {title}\n")?;
+            for (i, paragraph) in content.paragraphs.iter().enumerate() {
+                render_images(out, format, content, i)?;
+                writeln!(out, "{paragraph}\n")?;
+            }
+            render_images(out, format, content, content.paragraphs.len())?;
+            render_sections(out, format, &content.sections)
+        }
+        ExportFormat::Html | ExportFormat::Epub => {
+            writeln!(out, "<h3>{}</h3>", escape_html(title))?;
+            if let Some(release) = chapter.release_date {
+                writeln!(
+                    out,
+                    "<p class=\"release\"><time datetime=\"{}\">{release}</time></p>",
+                    release.date_time().to_rfc3339()
+                )?;
+            }
+            for (i, paragraph) in content.paragraphs.iter().enumerate() {
+                render_images(out, format, content, i)?;
+                writeln!(out, "<p>{}</p>", escape_html(paragraph))?;
+            }
+            render_images(out, format, content, content.paragraphs.len())?;
+            render_sections(out, format, &content.sections)
+        }
+    }
+}
+
+/// 第 `after` 段后面的插图, 最后一段后面的也一起
+fn render_images(
+    out: &mut String,
+    format: ExportFormat,
+    content: &ChapterContent,
+    after: usize,
+) -> std::fmt::Result {
+    let last = after == content.paragraphs.len();
+    for image in content
+        .images
+        .iter()
+        .filter(|image| image.after == after || (last && image.after > after))
+    {
+        match format {
+            ExportFormat::Txt => {}
+            ExportFormat::Markdown => writeln!(out, "![{}]({})\n", image.alt, image.url)?,
+            // 自闭合, epub 里的 xhtml 也能用
+            ExportFormat::Html | ExportFormat::Epub => writeln!(
+                out,
+                "<figure><img src=\"{}\" alt=\"{}\" /></figure>",
+                escape_html(&image.url),
+                escape_html(&image.alt)
+            )?,
+        }
+    }
+    Ok(())
+}
+
+fn section_class(kind: SectionKind) -> &'static str {
+    match kind {
+        SectionKind::AuthorNote => "author-note",
+        SectionKind::Appeal => "appeal",
+        SectionKind::Announcement => "announcement",
+    }
+}
+
+/// 章节后面的作者的话之类的, txt 里空一行隔开, markdown 里是引用, html 里是 `<aside>`
+fn render_sections(
+    out: &mut String,
+    format: ExportFormat,
+    sections: &[ExtraSection],
+) -> std::fmt::Result {
+    for section in sections {
+        match format {
+            ExportFormat::Txt => {
+                writeln!(out)?;
+                for paragraph in &section.paragraphs {
+                    writeln!(out, "　　{paragraph}")?;
+                }
+            }
+            ExportFormat::Markdown => {
+                for paragraph in &section.paragraphs {
+                    writeln!(out, "> {paragraph}\n")?;
+                }
+            }
+            ExportFormat::Html | ExportFormat::Epub => {
+                writeln!(out, "<aside class=\"{}\">", section_class(section.kind))?;
+                for paragraph in &section.paragraphs {
+                    writeln!(out, "<p>{}</p>", escape_html(paragraph))?;
+                }
+                writeln!(out, "</aside>")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 书最后的附录, 按章节列出挪过来的部分
+pub fn render_appendix(
+    out: &mut String,
+    format: ExportFormat,
+    heading: &str,
+    appendix: &[(String, Vec<ExtraSection>)],
+) -> std::fmt::Result {
+    match format {
+        ExportFormat::Txt => writeln!(out, "\n{heading}\n")?,
+        ExportFormat::Markdown => writeln!(out, "\n## {heading}\n")?,
+        ExportFormat::Html | ExportFormat::Epub => {
+            writeln!(out, "<h2>{}</h2>", escape_html(heading))?
+        }
+    }
+    for (title, sections) in appendix {
+        match format {
+            ExportFormat::Txt => writeln!(out, "{title}")?,
+            ExportFormat::Markdown => writeln!(out, "### {title}\n")?,
+            ExportFormat::Html | ExportFormat::Epub => {
+                writeln!(out, "<h3>{}</h3>", escape_html(title))?
+            }
+        }
+        render_sections(out, format, sections)?;
+        if format == ExportFormat::Txt {
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// 导出到 `dir/<书名>.<扩展名>`, 返回文件路径. `dir` 是书目录, 插图从这里找
+pub fn export_book(
+    book: &BookInfo,
+    sink: &dyn ChapterSink,
+    format: ExportFormat,
+    options: &ExportOptions,
+    dir: &Path,
+) -> anyhow::Result<(PathBuf, ExportStats)> {
+    let title = match &options.converter {
+        Some(converter) => converter.convert_book(book).display_title().to_string(),
+        None => book.display_title().to_string(),
+    };
+    let path = dir.join(format!("{}.{}", sanitize(&title), format.extension()));
+    let stats = if format == ExportFormat::Epub {
+        epub::write_epub(book, sink, options, dir, &path)?
+    } else {
+        let (text, stats) = render_book(book, sink, format, options)?;
+        write_atomic(&path, text)?;
+        stats
+    };
+    Ok((path, stats))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        books::BookVolume,
+        ids::{BookId, VolumeId},
+        storage::{ChapterKey, SqliteSink},
+    };
+
+    #[test]
+    fn test_render_book() {
+        let book = BookInfo {
+            id: BookId::new("1036741406"),
+            title: "围棋：我和AI五五开".to_string(),
+            volumes: vec![BookVolume {
+                title: "正文卷".to_string(),
+                is_vip: false,
+                id: VolumeId::new("vol108613887"),
+                chapters: vec![
+                    BookChapter::new(
+                        "1.应杰".to_string(),
+                        4,
+                        None,
+                        ChapterId::new("1"),
+                        String::new(),
+                    ),
+                    BookChapter::new(
+                        "2.时代的眼泪".to_string(),
+                        4,
+                        None,
+                        ChapterId::new("2"),
+                        String::new(),
+                    ),
+                ],
+            }],
+        };
+        let mut sink =
+            SqliteSink::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let volume = &book.volumes[0];
+        sink.put(
+            &ChapterKey::new(0, volume, 0, &volume.chapters[0]),
+            "<h1>1.应杰</h1><p>第一段</p><p>a < b</p>",
+        )
+        .unwrap();
+
+        let (txt, stats) =
+            render_book(&book, &sink, ExportFormat::Txt, &ExportOptions::default()).unwrap();
+        assert_eq!(stats.chapters, 1);
+        assert_eq!(stats.missing, vec!["2"]);
+        assert!(txt.contains("1.应杰\n\n　　第一段\n　　a < b\n"));
+
+        let mut locked_book = book.clone();
+        locked_book.volumes[0].chapters[1].access = crate::books::ChapterAccess::Locked;
+        let (txt, stats) = render_book(
+            &locked_book,
+            &sink,
+            ExportFormat::Txt,
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        assert!(stats.missing.is_empty());
+        assert_eq!(stats.locked, vec!["2"]);
+        assert!(txt.contains("2.时代的眼泪\n\n　　（本章未订阅, 没有正文）\n"));
+
+        let (html, _) =
+            render_book(&book, &sink, ExportFormat::Html, &ExportOptions::default()).unwrap();
+        assert!(html.contains("<h3>1.应杰</h3>\n<p>第一段</p>\n<p>a &lt; b</p>"));
+
+        let options = ExportOptions {
+            cleaner: Cleaner::builtin(),
+            ..Default::default()
+        };
+        sink.put(
+            &ChapterKey::new(0, volume, 1, &volume.chapters[1]),
+            "<p>\u{200b}　　他说,走吧...</p>",
+        )
+        .unwrap();
+        let (txt, _) = render_book(&book, &sink, ExportFormat::Txt, &options).unwrap();
+        assert!(txt.contains("2.时代的眼泪\n\n　　他说，走吧……\n"));
+
+        let (md, _) = render_book(
+            &book,
+            &sink,
+            ExportFormat::Markdown,
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        assert!(md.starts_with("# 围棋：我和AI五五开\n"));
+        assert!(md.contains("## 正文卷\n\n### 1.应杰\n\n第一段\n\n"));
+
+        let options = ExportOptions {
+            converter: Some(Converter::new(crate::zhconv::Conversion::S2TW)),
+            ..Default::default()
+        };
+        let (html, _) = render_book(&book, &sink, ExportFormat::Html, &options).unwrap();
+        assert!(html.contains("<html lang=\"zh-TW\">"));
+        assert!(html.contains("<title>圍棋：我和AI五五開</title>"));
+        assert!(html.contains("<h2>正文卷</h2>\n<h3>1.應傑</h3>\n<p>第一段</p>"));
+        assert!(html.contains("<h3>2.時代的眼淚</h3>"));
+    }
+
+    #[test]
+    fn test_render_images() {
+        let book = BookInfo {
+            id: BookId::new("1036741406"),
+            title: "书".to_string(),
+            volumes: vec![BookVolume {
+                title: "正文卷".to_string(),
+                is_vip: false,
+                id: VolumeId::new("vol1"),
+                chapters: vec![BookChapter::new(
+                    "3.地图".to_string(),
+                    4,
+                    None,
+                    ChapterId::new("3"),
+                    String::new(),
+                )],
+            }],
+        };
+        let mut sink =
+            SqliteSink::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let volume = &book.volumes[0];
+        sink.put(
+            &ChapterKey::new(0, volume, 0, &volume.chapters[0]),
+            r#"<p>第一段</p><img src="https://img.qidian.com/map.png" alt="地图"><p>第二段</p><img src="https://img.qidian.com/gone.png">"#,
+        )
+        .unwrap();
+        let mut options = ExportOptions::default();
+        options.images.files.insert(
+            "https://img.qidian.com/map.png".to_string(),
+            "abc.png".to_string(),
+        );
+
+        let (html, stats) = render_book(&book, &sink, ExportFormat::Html, &options).unwrap();
+        assert!(html.contains(
+            "<p>第一段</p>\n<figure><img src=\"images/abc.png\" alt=\"地图\" /></figure>\n<p>第二段</p>\n<figure><img src=\"https://img.qidian.com/gone.png\" alt=\"\" /></figure>"
+        ));
+        assert_eq!(
+            stats.missing_images,
+            vec!["https://img.qidian.com/gone.png"]
+        );
+
+        let (md, _) = render_book(&book, &sink, ExportFormat::Markdown, &options).unwrap();
+        assert!(md.contains("第一段\n\n![地图](images/abc.png)\n\n第二段\n\n"));
+        let (txt, _) = render_book(&book, &sink, ExportFormat::Txt, &options).unwrap();
+        assert!(!txt.contains("images/"));
+
+        // 转换只管文字, 地址和标签不动
+        sink.put(
+            &ChapterKey::new(0, volume, 0, &volume.chapters[0]),
+            r#"<p>里面</p><img src="https://img.qidian.com/地图.png" alt="地图">"#,
+        )
+        .unwrap();
+        let options = ExportOptions {
+            converter: Some(Converter::new(crate::zhconv::Conversion::S2T)),
+            ..Default::default()
+        };
+        let (html, _) = render_book(&book, &sink, ExportFormat::Html, &options).unwrap();
+        assert!(html.contains(
+            "<h3>3.地圖</h3>\n<p>裏面</p>\n<figure><img src=\"https://img.qidian.com/地图.png\" alt=\"地图\" /></figure>"
+        ));
+        let (md, _) = render_book(&book, &sink, ExportFormat::Markdown, &options).unwrap();
+        assert!(md.contains("![地图](https://img.qidian.com/地图.png)"));
+    }
+
+    #[test]
+    fn test_render_extras() {
+        let chapter = |id: &str, title: &str| {
+            BookChapter::new(
+                title.to_string(),
+                4,
+                None,
+                ChapterId::new(id),
+                String::new(),
+            )
+        };
+        let book = BookInfo {
+            id: BookId::new("1036741406"),
+            title: "书".to_string(),
+            volumes: vec![BookVolume {
+                title: "正文卷".to_string(),
+                is_vip: false,
+                id: VolumeId::new("vol1"),
+                chapters: vec![chapter("1", "1.应杰"), chapter("2", "请假条")],
+            }],
+        };
+        let mut sink =
+            SqliteSink::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let volume = &book.volumes[0];
+        sink.put(
+            &ChapterKey::new(0, volume, 0, &volume.chapters[0]),
+            "<h1>1.应杰</h1><p>第一段</p><p>PS：求月票</p>",
+        )
+        .unwrap();
+        sink.put(
+            &ChapterKey::new(0, volume, 1, &volume.chapters[1]),
+            "<h1>请假条</h1><p>今天请假</p>",
+        )
+        .unwrap();
+
+        let render = |extras, skip_announcements| {
+            let options = ExportOptions {
+                extras,
+                skip_announcements,
+                ..Default::default()
+            };
+            render_book(&book, &sink, ExportFormat::Txt, &options).unwrap()
+        };
+        let (kept, stats) = render(SectionPolicy::Keep, false);
+        assert_eq!(stats.chapters, 2);
+        assert!(kept.contains("　　第一段\n\n　　PS：求月票\n"));
+
+        let (appendix, _) = render(SectionPolicy::Appendix, false);
+        assert!(appendix.contains("　　第一段\n\n请假条"));
+        assert!(appendix.ends_with("附录\n\n1.应杰\n\n　　PS：求月票\n\n"));
+
+        let (dropped, stats) = render(SectionPolicy::Drop, true);
+        assert!(!dropped.contains("求月票"));
+        assert!(!dropped.contains("今天请假"));
+        assert_eq!(stats.skipped, vec!["2"]);
+    }
+}
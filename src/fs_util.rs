@@ -0,0 +1,173 @@
+//! 文件读写的小工具
+//!
+//! 所有输出文件都先写临时文件, fsync 后再 rename, 进程中途挂掉也不会留下写了一半的文件
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+
+/// 原子地写入文件
+pub fn write_atomic(path: &Path, data: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    write_atomic_with(path, data.as_ref(), &mut OpenOptions::new())
+}
+
+/// 原子地写入只有自己能读写的文件 (cookie 之类)
+pub fn write_private(path: &Path, data: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    write_atomic_with(path, data.as_ref(), &mut options)
+}
+
+/// 读文本文件, `-` 表示从标准输入读
+pub fn read_input(path: &str) -> anyhow::Result<String> {
+    if path == "-" {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+            .context("读取标准输入失败")?;
+        return Ok(text);
+    }
+    std::fs::read_to_string(path).with_context(|| format!("读取 {path} 失败"))
+}
+
+/// rename 之后把 `path` 所在的目录也 fsync, rename 本身才算落盘
+pub fn sync_parent(path: &Path) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn write_atomic_with(path: &Path, data: &[u8], options: &mut OpenOptions) -> anyhow::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .with_context(|| format!("文件路径无效: {path:?}"))?;
+    // 带上 pid, 两个进程同时写同一个文件也不会互相踩临时文件
+    let tmp_path = dir.join(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    let result = (|| {
+        let mut file = options
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&tmp_path, path)?;
+        sync_parent(path)?;
+        anyhow::Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result.with_context(|| format!("写入 {path:?} 失败"))
+}
+
+/// 输出目录上的进程锁
+///
+/// 只是 advisory lock, 防止两个下载进程同时往同一本书的目录里写; 锁随着这个值 drop 释放
+#[derive(Debug)]
+pub struct DirLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl DirLock {
+    pub const FILE_NAME: &str = ".lock";
+
+    /// 锁住 `dir`, 已经被别的进程锁住时报错
+    pub fn acquire(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(Self::FILE_NAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let owner = std::fs::read_to_string(&path).unwrap_or_default();
+                bail!(
+                    "输出目录 {dir:?} 正被另一个进程使用 (pid {}), 请等它结束或换一个目录",
+                    owner.trim()
+                );
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(Self { path, _file: file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("qidian-fs-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = temp_dir("write");
+        let path = dir.join("chapter.html");
+        write_atomic(&path, "第一版").unwrap();
+        write_atomic(&path, "第二版").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "第二版");
+        // 不留临时文件
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["chapter.html"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let private = dir.join("cookie.json");
+            write_private(&private, "[]").unwrap();
+            let mode = std::fs::metadata(&private).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_dir_lock() {
+        let dir = temp_dir("lock");
+        let lock = DirLock::acquire(&dir).unwrap();
+        let err = DirLock::acquire(&dir).unwrap_err();
+        assert!(err.to_string().contains("另一个进程"), "{err}");
+        drop(lock);
+        DirLock::acquire(&dir).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
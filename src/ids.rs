@@ -0,0 +1,366 @@
+//! 书, 卷, 章的 id 和起点的链接
+//!
+//! 页面上的链接有各种写法: `//www.qidian.com/chapter/1036741406/748679604/`,
+//! `https://m.qidian.com/book/1036741406`, 带不带结尾的 `/` 都有, 统一在这里解析
+
+use std::{borrow::Borrow, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! id_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn new(id: impl Into<String>) -> Self {
+                Self(id.into())
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0.is_empty()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(id: &str) -> Self {
+                Self(id.to_string())
+            }
+        }
+
+        impl From<&$name> for $name {
+            fn from(id: &$name) -> Self {
+                id.clone()
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(id: String) -> Self {
+                Self(id)
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl rusqlite::ToSql for $name {
+            fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+                self.0.to_sql()
+            }
+        }
+
+        impl rusqlite::types::FromSql for $name {
+            fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+                String::column_result(value).map(Self)
+            }
+        }
+    };
+}
+
+id_type!(
+    /// 书的 id, 纯数字, 例如 `1036741406`
+    BookId
+);
+id_type!(
+    /// 卷的 id, 目录里 label 的 `for`, 例如 `vol108613887`
+    VolumeId
+);
+id_type!(
+    /// 章节的 id, 纯数字, 例如 `748679604`
+    ChapterId
+);
+
+fn is_numeric_id(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+fn invalid_id() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "Invalid id, expect digits",
+    )
+}
+
+impl FromStr for BookId {
+    type Err = std::io::Error;
+    /// 纯数字的 id, 或者书 / 章节的链接
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if is_numeric_id(s) {
+            return Ok(Self::new(s));
+        }
+        QidianUrl::parse(s)
+            .map(|url| url.book_id().clone())
+            .ok_or_else(invalid_id)
+    }
+}
+
+impl FromStr for ChapterId {
+    type Err = std::io::Error;
+    /// 纯数字的 id, 或者章节的链接
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if is_numeric_id(s) {
+            return Ok(Self::new(s));
+        }
+        match QidianUrl::parse(s) {
+            Some(QidianUrl::Chapter { chapter, .. }) => Ok(chapter),
+            _ => Err(invalid_id()),
+        }
+    }
+}
+
+/// 起点的书页或者章节页
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QidianUrl {
+    Book(BookId),
+    Chapter { book: BookId, chapter: ChapterId },
+}
+
+const HOSTS: &[&str] = &[
+    "www.qidian.com",
+    "qidian.com",
+    "m.qidian.com",
+    "book.qidian.com",
+];
+
+impl QidianUrl {
+    /// 解析各种写法的链接, 不是起点的书 / 章节链接返回 `None`
+    ///
+    /// 支持 `https://`, `http://`, `//` 开头或者没有协议的, 手机站 `m.qidian.com`,
+    /// 老的 `book.qidian.com/info/<书 id>`, 结尾的 `/`, `?` 查询参数和 `#`
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .or_else(|| url.strip_prefix("//"))
+            .unwrap_or(url);
+        let rest = rest.split(['?', '#']).next()?;
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if !HOSTS.contains(&host.to_ascii_lowercase().as_str()) {
+            return None;
+        }
+        let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match segments.as_slice() {
+            ["book" | "info", book, ..] if is_numeric_id(book) => {
+                Some(QidianUrl::Book(BookId::new(*book)))
+            }
+            ["chapter", book, chapter, ..] if is_numeric_id(book) && is_numeric_id(chapter) => {
+                Some(QidianUrl::Chapter {
+                    book: BookId::new(*book),
+                    chapter: ChapterId::new(*chapter),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn book_id(&self) -> &BookId {
+        match self {
+            QidianUrl::Book(book) | QidianUrl::Chapter { book, .. } => book,
+        }
+    }
+
+    pub fn chapter_id(&self) -> Option<&ChapterId> {
+        match self {
+            QidianUrl::Book(_) => None,
+            QidianUrl::Chapter { chapter, .. } => Some(chapter),
+        }
+    }
+
+    /// 不带协议和域名的路径, `/chapter/<书 id>/<章节 id>/`
+    pub fn path(&self) -> String {
+        match self {
+            QidianUrl::Book(book) => format!("/book/{book}/"),
+            QidianUrl::Chapter { book, chapter } => format!("/chapter/{book}/{chapter}/"),
+        }
+    }
+
+    /// 电脑版的完整链接
+    pub fn to_url(&self) -> String {
+        format!("https://www.qidian.com{}", self.path())
+    }
+
+    /// 手机版的完整链接
+    pub fn to_mobile_url(&self) -> String {
+        format!("https://m.qidian.com{}", self.path())
+    }
+
+    /// 在目录页里找这个链接的 css 选择器, 不管 href 带不带协议和结尾的 `/`
+    pub fn link_selector(&self) -> String {
+        let path = self.path();
+        let trimmed = path.trim_end_matches('/');
+        format!("a[href*='{path}'], a[href$='{trimmed}']")
+    }
+}
+
+impl fmt::Display for QidianUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_url())
+    }
+}
+
+impl FromStr for QidianUrl {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid qidian url, expect a book or chapter link",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chapter(book: &str, chapter: &str) -> Option<QidianUrl> {
+        Some(QidianUrl::Chapter {
+            book: BookId::new(book),
+            chapter: ChapterId::new(chapter),
+        })
+    }
+
+    #[test]
+    fn test_parse_chapter_urls() {
+        let expected = chapter("1036741406", "748679604");
+        for url in [
+            "//www.qidian.com/chapter/1036741406/748679604/",
+            "//www.qidian.com/chapter/1036741406/748679604",
+            "https://www.qidian.com/chapter/1036741406/748679604/",
+            "http://www.qidian.com/chapter/1036741406/748679604/",
+            "www.qidian.com/chapter/1036741406/748679604/",
+            "https://m.qidian.com/chapter/1036741406/748679604/",
+            "https://www.qidian.com/chapter/1036741406/748679604/?from=catalog#comments",
+            "  https://WWW.qidian.com/chapter/1036741406/748679604/  ",
+        ] {
+            assert_eq!(QidianUrl::parse(url), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_parse_book_urls() {
+        let expected = Some(QidianUrl::Book(BookId::new("1036741406")));
+        for url in [
+            "https://www.qidian.com/book/1036741406/",
+            "https://www.qidian.com/book/1036741406",
+            "//www.qidian.com/book/1036741406/",
+            "https://m.qidian.com/book/1036741406/catalog",
+            "https://book.qidian.com/info/1036741406/",
+            "qidian.com/book/1036741406",
+        ] {
+            assert_eq!(QidianUrl::parse(url), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_urls() {
+        for url in [
+            "",
+            "https://www.qidian.com/",
+            "https://www.qidian.com/book/abc/",
+            "https://www.qidian.com/chapter/1036741406/",
+            "https://www.qidian.com/chapter/1036741406/abc/",
+            "https://evil.com/book/1036741406/",
+            "https://www.qidian.com.evil.com/book/1036741406/",
+            "/chapter/1036741406/748679604/",
+        ] {
+            assert_eq!(QidianUrl::parse(url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_build_urls() {
+        let url = chapter("1036741406", "748679604").unwrap();
+        assert_eq!(
+            url.to_url(),
+            "https://www.qidian.com/chapter/1036741406/748679604/"
+        );
+        assert_eq!(
+            url.to_mobile_url(),
+            "https://m.qidian.com/chapter/1036741406/748679604/"
+        );
+        assert_eq!(url.book_id(), "1036741406");
+        assert_eq!(url.chapter_id().unwrap(), "748679604");
+        assert_eq!(
+            url.link_selector(),
+            "a[href*='/chapter/1036741406/748679604/'], a[href$='/chapter/1036741406/748679604']"
+        );
+        // 解析再生成是同一个链接
+        assert_eq!(QidianUrl::parse(&url.to_url()), Some(url.clone()));
+        assert_eq!(QidianUrl::parse(&url.to_mobile_url()), Some(url));
+
+        let book = QidianUrl::Book(BookId::new("1036741406"));
+        assert_eq!(book.to_url(), "https://www.qidian.com/book/1036741406/");
+        assert_eq!(book.chapter_id(), None);
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        assert_eq!("1036741406".parse::<BookId>().unwrap(), "1036741406");
+        assert_eq!(
+            "https://www.qidian.com/book/1036741406/"
+                .parse::<BookId>()
+                .unwrap(),
+            "1036741406"
+        );
+        assert_eq!(
+            "//www.qidian.com/chapter/1036741406/748679604/"
+                .parse::<ChapterId>()
+                .unwrap(),
+            "748679604"
+        );
+        assert!("abc".parse::<BookId>().is_err());
+        assert!(
+            "https://www.qidian.com/book/1036741406/"
+                .parse::<ChapterId>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_id_serde() {
+        let id = ChapterId::new("748679604");
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""748679604""#);
+        let map: std::collections::BTreeMap<ChapterId, u32> =
+            serde_json::from_str(r#"{"748679604": 1}"#).unwrap();
+        assert_eq!(map.get("748679604"), Some(&1));
+        assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"748679604":1}"#);
+    }
+}
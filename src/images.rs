@@ -0,0 +1,123 @@
+//! 章节里的插图
+//!
+//! 图片存在书目录的 `images/` 下面, 文件名是内容的 sha256, 同一张图出现在几章里也只存一份.
+//! `images.json` 记着每个图片地址对应哪个文件, 导出时用它把地址换成本地文件
+//!
+//! HTML 和 Markdown 导出引用这里的文件, EPUB 导出把用到的图片打包进书里
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::fs_util::write_atomic;
+
+/// 图片文件放在书目录下的这个目录里
+pub const IMAGE_DIR: &str = "images";
+
+/// 按文件头猜扩展名, 认不出来的用 `bin`
+fn extension(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [0xff, 0xd8, 0xff, ..] => "jpg",
+        [b'G', b'I', b'F', b'8', ..] => "gif",
+        _ if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") => "webp",
+        [b'B', b'M', ..] => "bmp",
+        _ if bytes.trim_ascii_start().starts_with(b"<svg")
+            || bytes.trim_ascii_start().starts_with(b"<?xml") =>
+        {
+            "svg"
+        }
+        _ => "bin",
+    }
+}
+
+/// 图片地址 -> 本地文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageIndex {
+    /// 地址 -> `images/` 下的文件名
+    pub files: BTreeMap<String, String>,
+}
+
+impl ImageIndex {
+    pub const FILE_NAME: &str = "images.json";
+
+    /// 读取书目录下的索引, 没有就是空的
+    pub fn load(book_dir: &Path) -> anyhow::Result<Self> {
+        let path = book_dir.join(Self::FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, book_dir: &Path) -> anyhow::Result<()> {
+        write_atomic(
+            &book_dir.join(Self::FILE_NAME),
+            serde_json::to_string_pretty(self)?,
+        )
+    }
+
+    /// 已经下载过, 并且文件还在
+    pub fn contains(&self, book_dir: &Path, url: &str) -> bool {
+        self.files
+            .get(url)
+            .is_some_and(|file| book_dir.join(IMAGE_DIR).join(file).exists())
+    }
+
+    /// 相对书目录的路径, 比如 `images/<hash>.png`
+    pub fn local_path(&self, url: &str) -> Option<String> {
+        self.files
+            .get(url)
+            .map(|file| format!("{IMAGE_DIR}/{file}"))
+    }
+
+    /// 保存一张图片, 内容一样的图片只写一次, 返回相对书目录的路径
+    pub fn store(&mut self, book_dir: &Path, url: &str, bytes: &[u8]) -> anyhow::Result<String> {
+        let file = format!("{:x}.{}", Sha256::digest(bytes), extension(bytes));
+        let path = book_dir.join(IMAGE_DIR).join(&file);
+        if !path.exists() {
+            std::fs::create_dir_all(book_dir.join(IMAGE_DIR))?;
+            write_atomic(&path, bytes)?;
+        }
+        self.files.insert(url.to_string(), file);
+        Ok(self.local_path(url).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_store_dedup() {
+        let dir = std::env::temp_dir().join(format!("qidian-images-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let png = b"\x89PNG\r\n\x1a\nfake";
+        let mut index = ImageIndex::default();
+        let first = index
+            .store(&dir, "https://img.qidian.com/a.png", png)
+            .unwrap();
+        let second = index
+            .store(&dir, "https://img.qidian.com/b.png?v=2", png)
+            .unwrap();
+        assert_eq!(first, second);
+        assert!(first.starts_with("images/") && first.ends_with(".png"));
+        assert_eq!(std::fs::read_dir(dir.join(IMAGE_DIR)).unwrap().count(), 1);
+        assert!(index.contains(&dir, "https://img.qidian.com/b.png?v=2"));
+        assert!(!index.contains(&dir, "https://img.qidian.com/c.png"));
+
+        index.save(&dir).unwrap();
+        let loaded = ImageIndex::load(&dir).unwrap();
+        assert_eq!(
+            loaded.local_path("https://img.qidian.com/a.png"),
+            Some(first)
+        );
+        assert_eq!(extension(b"\xff\xd8\xff\xe0"), "jpg");
+        assert_eq!(extension(b"hello"), "bin");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
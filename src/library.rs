@@ -0,0 +1,602 @@
+//! 书库: 把下载过的书放进一个 sqlite 数据库里做全文搜索
+//!
+//! 中文没有空格分词, 这里把正文切成相邻两个字的 bigram 交给 fts5 建索引,
+//! 查询按空白分词, 每个词切成 bigram 当短语查, 相当于子串匹配; 单个字的词退回 `LIKE`
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use rusqlite::{Connection, OptionalExtension, params, types::Value};
+use tracing::{info, warn};
+
+use crate::{
+    books::{BookChapter, BookInfo, BookVolume},
+    ids::{BookId, ChapterId, VolumeId},
+    manifest::Manifest,
+    parse_page::chapter_content,
+    storage::{self, ChapterSink},
+};
+
+/// 下载时和章节存在一起的书目录文件
+pub const CATALOG_FILE: &str = "catalog.json";
+
+/// 搜索结果前后各带多少字
+const SNIPPET_CONTEXT: usize = 30;
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2ffff}')
+}
+
+/// 把文本切成 fts5 能用的词
+///
+/// 连续的汉字切成 bigram ("围棋高手" -> "围棋 棋高 高手"), 只有一个字就保留这个字;
+/// 字母数字按词保留并转小写; 其他字符当分隔符
+pub fn ngrams(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cjk_run: Vec<char> = Vec::new();
+    let mut word = String::new();
+
+    let flush_cjk = |run: &mut Vec<char>, tokens: &mut Vec<String>| {
+        match run.len() {
+            0 => {}
+            1 => tokens.push(run[0].to_string()),
+            _ => tokens.extend(run.windows(2).map(|w| w.iter().collect())),
+        }
+        run.clear();
+    };
+    let flush_word = |word: &mut String, tokens: &mut Vec<String>| {
+        if !word.is_empty() {
+            tokens.push(std::mem::take(word));
+        }
+    };
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            cjk_run.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            flush_word(&mut word, &mut tokens);
+        }
+    }
+    flush_cjk(&mut cjk_run, &mut tokens);
+    flush_word(&mut word, &mut tokens);
+    tokens
+}
+
+/// 把查询变成 fts5 的 MATCH 表达式
+///
+/// 按空白分成几个词, 每个词的 bigram 序列是一个短语, 短语之间是 AND;
+/// 切不出 bigram 的词 (见 [`needs_like`]) 不放进去, 一个短语都没有时返回 `None`
+pub fn match_query(query: &str) -> Option<String> {
+    let phrases: Vec<_> = query
+        .split_whitespace()
+        .filter(|term| !needs_like(term))
+        .map(|term| format!("\"{}\"", ngrams(term).join(" ").replace('"', "\"\"")))
+        .collect();
+    (!phrases.is_empty()).then(|| phrases.join(" AND "))
+}
+
+/// 这个词 bigram 索引找不到 (只有单个的汉字), 要用 `LIKE` 查
+fn needs_like(term: &str) -> bool {
+    let tokens = ngrams(term);
+    tokens
+        .iter()
+        .all(|t| t.chars().count() == 1 && is_cjk_str(t))
+}
+
+fn is_cjk_str(s: &str) -> bool {
+    s.chars().all(is_cjk)
+}
+
+/// 在 `text` 里找 `query` 里最先出现的词, 截出前后一段
+pub fn snippet(text: &str, query: &str) -> String {
+    let flat: String = text.split('\n').collect::<Vec<_>>().join(" ");
+    let lower = flat.to_lowercase();
+    let query = query.to_lowercase();
+    let chars: Vec<char> = flat.chars().collect();
+    let (hit, len) = query
+        .split_whitespace()
+        .filter_map(|term| {
+            let byte = lower.find(term)?;
+            Some((lower[..byte].chars().count(), term.chars().count()))
+        })
+        .min()
+        .unwrap_or((0, 0));
+    let start = hit.saturating_sub(SNIPPET_CONTEXT);
+    let end = (hit + len + SNIPPET_CONTEXT).min(chars.len());
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    out.extend(&chars[start..end]);
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// 一条搜索结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub book_title: String,
+    pub volume_title: String,
+    pub chapter_title: String,
+    pub chapter_id: ChapterId,
+    pub snippet: String,
+}
+
+pub struct Library {
+    conn: Connection,
+}
+
+impl Library {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::from_connection(
+            Connection::open(path).with_context(|| format!("打开书库 {path:?} 失败"))?,
+        )
+    }
+
+    pub fn from_connection(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA foreign_keys = ON;
+             CREATE TABLE IF NOT EXISTS books (
+                 id    TEXT PRIMARY KEY,
+                 title TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS volumes (
+                 id      TEXT PRIMARY KEY,
+                 book_id TEXT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+                 idx     INTEGER NOT NULL,
+                 title   TEXT NOT NULL,
+                 is_vip  INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS chapters (
+                 id           TEXT PRIMARY KEY,
+                 book_id      TEXT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+                 volume_id    TEXT NOT NULL,
+                 idx          INTEGER NOT NULL,
+                 title        TEXT NOT NULL,
+                 length       INTEGER NOT NULL,
+                 release_date TEXT NOT NULL,
+                 url          TEXT NOT NULL,
+                 text         TEXT NOT NULL
+             );
+             CREATE VIRTUAL TABLE IF NOT EXISTS chapter_fts USING fts5(grams);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    pub fn upsert_book(&self, book: &BookInfo) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO books (id, title) VALUES (?1, ?2)
+             ON CONFLICT(id) DO UPDATE SET title = excluded.title",
+            params![book.id, book.title],
+        )?;
+        for (idx, volume) in book.volumes.iter().enumerate() {
+            self.upsert_volume(&book.id, idx, volume)?;
+        }
+        Ok(())
+    }
+
+    fn upsert_volume(
+        &self,
+        book_id: &BookId,
+        idx: usize,
+        volume: &BookVolume,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO volumes (id, book_id, idx, title, is_vip) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(id) DO UPDATE SET
+                 book_id = excluded.book_id, idx = excluded.idx,
+                 title = excluded.title, is_vip = excluded.is_vip",
+            params![volume.id, book_id, idx as i64, volume.title, volume.is_vip],
+        )?;
+        Ok(())
+    }
+
+    /// 写入一章的正文并更新全文索引
+    pub fn upsert_chapter(
+        &self,
+        book_id: &BookId,
+        volume_id: &VolumeId,
+        idx: usize,
+        chapter: &BookChapter,
+        text: &str,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO chapters
+                 (id, book_id, volume_id, idx, title, length, release_date, url, text)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(id) DO UPDATE SET
+                 book_id = excluded.book_id, volume_id = excluded.volume_id,
+                 idx = excluded.idx, title = excluded.title, length = excluded.length,
+                 release_date = excluded.release_date, url = excluded.url, text = excluded.text",
+            params![
+                chapter.id,
+                book_id,
+                volume_id,
+                idx as i64,
+                chapter.title,
+                chapter.length,
+                chapter
+                    .release_date
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+                chapter.url,
+                text
+            ],
+        )?;
+        let rowid: i64 = self.conn.query_row(
+            "SELECT rowid FROM chapters WHERE id = ?1",
+            [&chapter.id],
+            |row| row.get(0),
+        )?;
+        let grams = ngrams(&format!("{}\n{text}", chapter.title)).join(" ");
+        self.conn
+            .execute("DELETE FROM chapter_fts WHERE rowid = ?1", [rowid])?;
+        self.conn.execute(
+            "INSERT INTO chapter_fts (rowid, grams) VALUES (?1, ?2)",
+            params![rowid, grams],
+        )?;
+        Ok(())
+    }
+
+    /// 全文搜索, 按相关度排序
+    pub fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let select = "SELECT b.title, COALESCE(v.title, ''), c.title, c.id, c.text
+             FROM chapters c
+             JOIN books b ON b.id = c.book_id
+             LEFT JOIN volumes v ON v.id = c.volume_id";
+        let map_row = |row: &rusqlite::Row<'_>| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, ChapterId>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        };
+        // fts5 查能切出 bigram 的词, 剩下的单字每个加一个 LIKE 条件
+        let mut joins = String::new();
+        let mut conditions = Vec::new();
+        let mut args = Vec::new();
+        let fts_query = match_query(query);
+        if let Some(fts_query) = fts_query.clone() {
+            joins.push_str(" JOIN chapter_fts f ON f.rowid = c.rowid");
+            args.push(Value::Text(fts_query));
+            conditions.push(format!("chapter_fts MATCH ?{}", args.len()));
+        }
+        for term in query.split_whitespace().filter(|term| needs_like(term)) {
+            args.push(Value::Text(format!("%{term}%")));
+            let n = args.len();
+            conditions.push(format!("(c.text LIKE ?{n} OR c.title LIKE ?{n})"));
+        }
+        let order = if fts_query.is_some() {
+            "f.rank"
+        } else {
+            "c.book_id, v.idx, c.idx"
+        };
+        args.push(Value::Integer(limit as i64));
+        let mut stmt = self.conn.prepare(&format!(
+            "{select}{joins} WHERE {} ORDER BY {order} LIMIT ?{}",
+            conditions.join(" AND "),
+            args.len()
+        ))?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(&args), map_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows
+            .into_iter()
+            .map(
+                |(book_title, volume_title, chapter_title, chapter_id, text)| SearchHit {
+                    snippet: snippet(&text, query),
+                    book_title,
+                    volume_title,
+                    chapter_title,
+                    chapter_id,
+                },
+            )
+            .collect())
+    }
+
+    pub fn book_title(&self, book_id: &BookId) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT title FROM books WHERE id = ?1", [book_id], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    /// 导入一本书的输出目录 (`<out>/<书 id>`), 返回导入的章节数
+    pub fn import_book_dir(&self, dir: &Path) -> anyhow::Result<usize> {
+        let sink = storage::open_existing(dir)?;
+        let mut book = load_catalog(dir, sink.as_ref())?;
+        let manifest_path = dir.join(Manifest::FILE_NAME);
+        if manifest_path.exists() {
+            Manifest::load_for(&manifest_path, &book.id)?.apply_locked(&mut book);
+        }
+        self.conn.execute_batch("BEGIN")?;
+        let result = (|| {
+            self.upsert_book(&book)?;
+            let mut count = 0;
+            for volume in &book.volumes {
+                for (idx, chapter) in volume.chapters.iter().enumerate() {
+                    if chapter.is_locked() {
+                        // 试读部分不进书库
+                        continue;
+                    }
+                    let Some(html) = sink.get(&chapter.id)? else {
+                        continue;
+                    };
+                    let text = chapter_content::parse(&html).text();
+                    self.upsert_chapter(&book.id, &volume.id, idx, chapter, &text)?;
+                    count += 1;
+                }
+            }
+            anyhow::Ok(count)
+        })();
+        match result {
+            Ok(count) => {
+                self.conn.execute_batch("COMMIT")?;
+                Ok(count)
+            }
+            Err(e) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// 读书的目录信息
+///
+/// 优先用下载时保存的 `catalog.json`; 老的输出目录没有的话, 就从存储里的章节和清单拼一个出来
+pub fn load_catalog(dir: &Path, sink: &dyn ChapterSink) -> anyhow::Result<BookInfo> {
+    let catalog_path = dir.join(CATALOG_FILE);
+    if catalog_path.exists() {
+        return Ok(serde_json::from_str(&std::fs::read_to_string(
+            &catalog_path,
+        )?)?);
+    }
+
+    let dir_name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let manifest_path = dir.join(Manifest::FILE_NAME);
+    let manifest = if manifest_path.exists() {
+        Some(Manifest::load_for(
+            &manifest_path,
+            &BookId::new(dir_name.as_str()),
+        )?)
+    } else {
+        None
+    };
+    let mut book = BookInfo {
+        id: BookId::new(dir_name.as_str()),
+        title: dir_name,
+        volumes: Vec::new(),
+    };
+    for key in sink.list()? {
+        let entry = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.chapters.get(&key.id));
+        if book
+            .volumes
+            .last()
+            .is_none_or(|volume| volume.title != key.volume_title)
+        {
+            book.volumes.push(BookVolume {
+                title: key.volume_title.clone(),
+                is_vip: false,
+                id: entry
+                    .map(|entry| entry.volume_id.clone())
+                    .unwrap_or_else(|| VolumeId::new(format!("{}#{}", book.id, key.volume_index))),
+                chapters: Vec::new(),
+            });
+        }
+        let chapter = BookChapter::new(
+            key.title.clone(),
+            entry.map(|entry| entry.length).unwrap_or_default(),
+            None,
+            key.id.clone(),
+            String::new(),
+        );
+        book.volumes.last_mut().unwrap().chapters.push(chapter);
+    }
+    Ok(book)
+}
+
+/// 找出 `path` 下面所有的书目录; `path` 本身就是书目录时只返回它自己
+pub fn find_book_dirs(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let is_book_dir = |dir: &Path| {
+        dir.join(CATALOG_FILE).exists()
+            || dir.join(Manifest::FILE_NAME).exists()
+            || dir.join(storage::ZipSink::FILE_NAME).exists()
+            || dir.join(storage::SqliteSink::FILE_NAME).exists()
+    };
+    if is_book_dir(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && is_book_dir(&entry.path()) {
+            dirs.push(entry.path());
+        }
+    }
+    if dirs.is_empty() && !storage::scan_dir(path)?.is_empty() {
+        // 最早的 out/ 布局, 卷目录直接放在 out 下面
+        dirs.push(path.to_path_buf());
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// `search` 子命令
+pub fn search(config: &crate::CliArg, query: &str, limit: usize) -> anyhow::Result<()> {
+    let library = Library::open(Path::new(&config.library))?;
+    let hits = library.search(query, limit)?;
+    if hits.is_empty() {
+        println!("没有找到 \"{query}\"");
+    }
+    for hit in hits {
+        println!(
+            "《{}》 {} / {} ({})\n    {}",
+            hit.book_title, hit.volume_title, hit.chapter_title, hit.chapter_id, hit.snippet
+        );
+    }
+    Ok(())
+}
+
+/// `library` 子命令
+pub fn run(config: &crate::CliArg, action: &crate::LibraryAction) -> anyhow::Result<()> {
+    let library = Library::open(Path::new(&config.library))?;
+    match action {
+        crate::LibraryAction::Import { paths } => {
+            for path in paths {
+                for dir in find_book_dirs(Path::new(path))? {
+                    match library.import_book_dir(&dir) {
+                        Ok(count) => {
+                            info!(dir = %dir.display(), count, "导入完成");
+                            println!("导入 {dir:?}: {count} 章");
+                        }
+                        Err(e) => warn!(dir = %dir.display(), "导入失败: {e:#}"),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ngrams() {
+        assert_eq!(ngrams("围棋高手"), vec!["围棋", "棋高", "高手"]);
+        assert_eq!(ngrams("我和AI五五开"), vec!["我和", "ai", "五五", "五开"]);
+        assert_eq!(ngrams("一, 二"), vec!["一", "二"]);
+        assert_eq!(ngrams("  "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(match_query("应杰").as_deref(), Some("\"应杰\""));
+        assert_eq!(
+            match_query("时代的眼泪").as_deref(),
+            Some("\"时代 代的 的眼 眼泪\"")
+        );
+        assert_eq!(match_query("杰"), None);
+        assert_eq!(match_query("AI").as_deref(), Some("\"ai\""));
+        assert_eq!(
+            match_query("围棋 阿尔法").as_deref(),
+            Some("\"围棋\" AND \"阿尔 尔法\"")
+        );
+        assert_eq!(
+            match_query("AlphaGo 围棋").as_deref(),
+            Some("\"alphago\" AND \"围棋\"")
+        );
+        // 单字的词交给 LIKE
+        assert_eq!(match_query("应杰 愣").as_deref(), Some("\"应杰\""));
+        assert_eq!(match_query("杰 愣"), None);
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = format!("{}应杰落下一子{}", "前".repeat(40), "后".repeat(40));
+        let snip = snippet(&text, "应杰");
+        assert!(snip.starts_with('…') && snip.ends_with('…'));
+        assert!(snip.contains("应杰落下一子"));
+        assert_eq!(snip.chars().count(), 30 + 2 + 30 + 2);
+    }
+
+    fn chapter(id: &str, title: &str) -> BookChapter {
+        BookChapter::new(
+            title.to_string(),
+            100,
+            "2023-04-03 10:19:10".parse().ok(),
+            ChapterId::new(id),
+            format!("//www.qidian.com/chapter/1036741406/{id}/"),
+        )
+    }
+
+    #[test]
+    fn test_search() {
+        let library = Library::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let book = BookInfo {
+            id: BookId::new("1036741406"),
+            title: "围棋：我和AI五五开".to_string(),
+            volumes: vec![BookVolume {
+                title: "正文卷".to_string(),
+                is_vip: false,
+                id: VolumeId::new("vol108613887"),
+                chapters: vec![chapter("1", "1.应杰"), chapter("2", "2.时代的眼泪")],
+            }],
+        };
+        library.upsert_book(&book).unwrap();
+        let volume = &book.volumes[0];
+        library
+            .upsert_chapter(
+                &book.id,
+                &volume.id,
+                0,
+                &volume.chapters[0],
+                "应杰第一次坐到棋盘前。",
+            )
+            .unwrap();
+        library
+            .upsert_chapter(
+                &book.id,
+                &volume.id,
+                1,
+                &volume.chapters[1],
+                "AI 落子如飞, 应杰愣住了。",
+            )
+            .unwrap();
+
+        let hits = library.search("应杰", 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].book_title, "围棋：我和AI五五开");
+        assert_eq!(hits[0].volume_title, "正文卷");
+
+        let hits = library.search("落子如飞", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chapter_id, "2");
+        assert!(hits[0].snippet.contains("落子如飞"));
+
+        // "子如" 出现了但 "子如棋" 没有
+        assert!(library.search("子如棋", 10).unwrap().is_empty());
+        // 单字走 LIKE
+        assert_eq!(library.search("愣", 10).unwrap().len(), 1);
+        // 几个词要都出现, 不用挨着
+        assert_eq!(library.search("应杰 棋盘", 10).unwrap().len(), 1);
+        assert_eq!(library.search("应杰 围棋", 10).unwrap().len(), 0);
+        let hits = library.search("AI 应杰", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chapter_id, "2");
+        assert_eq!(library.search("应杰 愣", 10).unwrap().len(), 1);
+        assert_eq!(library.search("应杰 飞", 10).unwrap().len(), 1);
+        // 重新导入同一章会替换索引
+        library
+            .upsert_chapter(&book.id, &volume.id, 1, &volume.chapters[1], "改过了")
+            .unwrap();
+        assert!(library.search("落子如飞", 10).unwrap().is_empty());
+    }
+}
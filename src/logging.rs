@@ -0,0 +1,103 @@
+//! 诊断日志
+//!
+//! 给人看的进度 (正在读哪一章, 等待登录之类) 还是直接 `println!` 到 stdout,
+//! 警告和错误这些走 tracing, 打到 stderr, 需要的话再写一份 json lines 到文件里
+
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::{CliArg, progress::StderrWriter};
+
+/// 根据 `-v` / `-q` 的次数决定终端日志级别
+pub fn level_from_verbosity(verbose: u8, quiet: u8) -> LevelFilter {
+    match verbose as i16 - quiet as i16 {
+        i16::MIN..=-2 => LevelFilter::ERROR,
+        -1 => LevelFilter::WARN,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        2..=i16::MAX => LevelFilter::TRACE,
+    }
+}
+
+/// 新建这次运行的日志文件, 不会覆盖同一秒里启动的别的进程的日志
+fn create_log_file(dir: &Path) -> anyhow::Result<(File, PathBuf)> {
+    let stem = format!("run-{}-{}", crate::cookies::now(), std::process::id());
+    for attempt in 0.. {
+        let path = match attempt {
+            0 => dir.join(format!("{stem}.jsonl")),
+            n => dir.join(format!("{stem}-{n}.jsonl")),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!()
+}
+
+/// 初始化日志, 返回本次运行的日志文件路径 (如果有)
+pub fn init(config: &CliArg) -> anyhow::Result<Option<PathBuf>> {
+    let stderr_layer = tracing_subscriber::fmt::layer()
+        .with_writer(|| StderrWriter)
+        .with_target(false)
+        .with_filter(level_from_verbosity(config.verbose, config.quiet));
+
+    let (file_layer, log_path) = match &config.log_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            let (file, path) = create_log_file(Path::new(dir))?;
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(LevelFilter::DEBUG);
+            (Some(layer), Some(path))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr_layer)
+        .with(file_layer)
+        .try_init()?;
+
+    if let Some(path) = &log_path {
+        tracing::info!(path = %path.display(), "日志写入文件");
+    }
+    Ok(log_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_level_from_verbosity() {
+        assert_eq!(level_from_verbosity(0, 0), LevelFilter::INFO);
+        assert_eq!(level_from_verbosity(1, 0), LevelFilter::DEBUG);
+        assert_eq!(level_from_verbosity(5, 0), LevelFilter::TRACE);
+        assert_eq!(level_from_verbosity(0, 1), LevelFilter::WARN);
+        assert_eq!(level_from_verbosity(0, 3), LevelFilter::ERROR);
+        assert_eq!(level_from_verbosity(1, 1), LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_create_log_file() {
+        let dir = std::env::temp_dir().join(format!("qidian-logs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (_, first) = create_log_file(&dir).unwrap();
+        let (_, second) = create_log_file(&dir).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,31 @@
 use std::str::FromStr;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 pub mod books;
+pub mod catalog;
+pub mod cleaner;
+pub mod cookies;
 pub mod drives;
+pub mod epub;
+pub mod export;
+pub mod fs_util;
+pub mod ids;
+pub mod images;
+pub mod library;
+pub mod logging;
+pub mod manifest;
 pub mod parse_page;
+pub mod progress;
+pub mod rate_limit;
+pub mod report;
+pub mod reprocess;
+pub mod revisions;
+pub mod shutdown;
+pub mod storage;
+pub mod verify;
+pub mod zhconv;
 
 const ABOUT: &str = "起点!";
 const LONG_ABOUT: &str = r#"boost !
@@ -35,6 +55,30 @@ impl FromStr for DriverType {
     }
 }
 
+/// 浏览器窗口大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for WindowSize {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid window size, expect WIDTHxHEIGHT",
+            )
+        };
+        let (width, height) = s.trim().split_once(['x', 'X', ',']).ok_or_else(invalid)?;
+        Ok(WindowSize {
+            width: width.trim().parse().map_err(|_| invalid())?,
+            height: height.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 #[command(version = VERSION, about = ABOUT, long_about = LONG_ABOUT, name = "qidian-downloader")]
 pub struct CliArg {
@@ -44,6 +88,47 @@ pub struct CliArg {
     #[arg(short = 'c', long = "cookie", default_value = "cookie.json")]
     /// cookie 存储文件的路径
     pub cookie_path: String,
+    #[arg(short = 'o', long = "out-dir", default_value = "out")]
+    /// 输出目录, 每本书在里面有自己的子目录
+    pub out_dir: String,
+    #[arg(
+        short = 's',
+        long = "storage",
+        default_value = "dir",
+        help = "章节的存储方式 (dir, zip, sqlite)"
+    )]
+    /// 章节的存储方式
+    ///
+    /// dir: 每章一个 html 文件; zip: 整本书一个 zip; sqlite: 整本书一个数据库
+    pub storage: storage::StorageKind,
+    #[arg(short = 'a', long = "account")]
+    /// 账号名, 不同账号的 cookie 分开存
+    ///
+    /// 指定后使用 `cookie.<账号>.json`
+    pub account: Option<cookies::AccountName>,
+    #[arg(long = "login-wait", default_value_t = 120, value_name = "SECS")]
+    /// 等待用户手动登录的最长时间 (秒)
+    pub login_wait: u64,
+    #[arg(long = "challenge-wait", default_value_t = 600, value_name = "SECS")]
+    /// 遇到人机验证时等待用户手动验证的最长时间 (秒)
+    pub challenge_wait: u64,
+    #[arg(long = "min-delay", default_value_t = 1500, value_name = "MS")]
+    /// 两次翻页之间至少间隔多少毫秒
+    pub min_delay_ms: u64,
+    #[arg(long = "jitter", default_value = "uniform:1500")]
+    /// 在最小间隔上额外加的随机时间
+    ///
+    /// 可选: none, uniform:<毫秒>, exp:<毫秒> (指数分布, 参数是均值)
+    pub jitter: rate_limit::Jitter,
+    #[arg(long = "chapters-per-hour", value_name = "N")]
+    /// 每小时最多存多少章 (打开失败的不算), 不填或 0 表示不限制
+    pub chapters_per_hour: Option<u32>,
+    #[arg(long = "pause-every", default_value = "50", value_name = "N")]
+    /// 每存多少章多歇一会, 0 表示不歇
+    pub pause_every: Option<u32>,
+    #[arg(long = "pause-secs", default_value_t = 30, value_name = "SECS")]
+    /// 定期休息多少秒
+    pub pause_secs: u64,
     #[arg(
         short = 't',
         long = "type",
@@ -56,19 +141,281 @@ pub struct CliArg {
     ///
     /// 暂不支持: firefox
     pub driver_type: DriverType,
+    #[arg(long = "headless")]
+    /// 以无头模式启动浏览器 (没有显示器的机器上用)
+    pub headless: bool,
+    #[arg(long = "window-size", value_name = "WIDTHxHEIGHT")]
+    /// 浏览器窗口大小, 例如 1920x1080
+    pub window_size: Option<WindowSize>,
+    #[arg(long = "profile-dir", value_name = "DIR")]
+    /// 浏览器的 user-data-dir
+    ///
+    /// 指定后登录状态会保存在浏览器配置里, 而不仅仅是 cookie 文件
+    pub profile_dir: Option<String>,
+    #[arg(long = "browser-arg", value_name = "ARG", allow_hyphen_values = true)]
+    /// 额外传给浏览器的启动参数, 可以多次指定
+    ///
+    /// 例如: --browser-arg=--proxy-server=http://127.0.0.1:7890
+    pub browser_args: Vec<String>,
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, global = true)]
+    /// 输出更多日志, 可以叠加 (-vv)
+    pub verbose: u8,
+    #[arg(short = 'q', long = "quiet", action = clap::ArgAction::Count, global = true)]
+    /// 只输出警告 (-q) 或错误 (-qq)
+    pub quiet: u8,
+    #[arg(long = "since", value_name = "TIME", global = true)]
+    /// 只处理这个时间 (北京时间, 含) 之后发布的章节, 例如 2023-04-01 或 "2023-04-01 12:00"
+    pub since: Option<books::ReleaseTime>,
+    #[arg(long = "until", value_name = "TIME", global = true)]
+    /// 只处理这个时间 (北京时间, 不含) 之前发布的章节
+    pub until: Option<books::ReleaseTime>,
+    #[arg(long = "skip-locked")]
+    /// 跳过目录里标着没订阅的章节, 不把付费提示页当正文存下来
+    pub skip_locked: bool,
+    #[arg(long = "only-locked")]
+    /// 只重新下载清单里记着没订阅的章节 (订阅之后用)
+    pub only_locked: bool,
+    #[arg(long = "log-dir", value_name = "DIR")]
+    /// 每次运行在这个目录下额外写一份 json lines 格式的日志
+    pub log_dir: Option<String>,
+    #[arg(long = "library", default_value = "library.sqlite", global = true)]
+    /// 书库数据库的路径, 给 `library` 和 `search` 用
+    pub library: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// 导入 / 导出 cookie
+    #[command(subcommand)]
+    Cookie(CookieAction),
+    /// 管理本地书库
+    #[command(subcommand)]
+    Library(LibraryAction),
+    /// 在书库里全文搜索
+    Search {
+        query: String,
+        #[arg(short = 'n', long = "limit", default_value_t = 20)]
+        /// 最多显示多少条
+        limit: usize,
+    },
+    /// 查看一章的历史版本和版本之间的差异
+    ///
+    /// 版本号 0 是当前版本, 默认比较最后一个历史版本和当前版本
+    Revisions {
+        book_id: ids::BookId,
+        chapter_id: ids::ChapterId,
+        #[arg(long = "from", value_name = "N")]
+        from: Option<u32>,
+        #[arg(long = "to", value_name = "N")]
+        to: Option<u32>,
+    },
+    /// 解析保存下来的书页或目录 html, 不需要浏览器
+    Catalog {
+        /// html 文件, `-` 表示从标准输入读
+        file: String,
+        #[arg(long = "json")]
+        /// 输出完整的 json
+        json: bool,
+    },
+    /// 不开浏览器, 重新处理以前下载好的输出目录
+    ///
+    /// 按目录名和文件名的序号还原卷和章的顺序, 重新生成清单, 检查并导出
+    Reprocess {
+        dir: String,
+        #[arg(long = "catalog", value_name = "FILE")]
+        /// 保存下来的书页或目录 html, `-` 表示从标准输入读
+        catalog: Option<String>,
+        #[arg(long = "book-id")]
+        /// 书的 id, 默认用目录名
+        book_id: Option<ids::BookId>,
+        #[arg(
+            short = 'f',
+            long = "format",
+            default_value = "txt",
+            help = "导出格式 (txt, markdown, html, epub), 可以多次指定"
+        )]
+        formats: Vec<export::ExportFormat>,
+        #[arg(
+            long = "extras",
+            default_value = "keep",
+            help = "作者的话, 求票这些怎么导出 (keep, appendix, drop)"
+        )]
+        /// 作者的话, 求票这些怎么导出
+        ///
+        /// keep: 留在章节后面; appendix: 挪到书最后的附录; drop: 不要
+        extras: parse_page::chapter_content::SectionPolicy,
+        #[arg(long = "skip-announcements")]
+        /// 不导出请假条, 上架感言之类的章节
+        skip_announcements: bool,
+        #[arg(long = "clean-config", value_name = "FILE")]
+        /// 整理文字的规则 (json), 不给就只用内置规则
+        clean_config: Option<String>,
+        #[arg(long = "no-clean")]
+        /// 不整理文字, 原样导出
+        no_clean: bool,
+        #[arg(
+            long = "convert",
+            value_name = "CONVERSION",
+            help = "简繁转换 (s2t, s2tw, s2hk, t2s, tw2s, hk2s)"
+        )]
+        /// 简繁转换, 书名, 目录和正文都转
+        convert: Option<zhconv::Conversion>,
+    },
+    /// 用整理规则处理一章保存下来的 html, 输出整理后的文字
+    Clean {
+        /// html 文件, `-` 表示从标准输入读
+        file: String,
+        #[arg(long = "config", value_name = "FILE")]
+        /// 整理文字的规则 (json), 不给就只用内置规则
+        config: Option<String>,
+        #[arg(long = "dry-run")]
+        /// 不输出文字, 显示每条规则改了什么
+        dry_run: bool,
+    },
+    /// 按目录里的字数检查下载好的章节, 有问题时退出码是 2
+    Verify {
+        dir: String,
+        #[arg(
+            long = "tolerance",
+            default_value_t = 15.0,
+            value_name = "PERCENT",
+            value_parser = verify::parse_tolerance
+        )]
+        /// 实际字数和目录字数差多少 (百分比) 以内算正常
+        tolerance: f64,
+        #[arg(long = "min-chars", default_value_t = 200, value_name = "N")]
+        /// 少于这么多字算太短
+        min_chars: usize,
+        #[arg(long = "json")]
+        /// 输出 json 格式的报告
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum LibraryAction {
+    /// 把下载好的书导入书库
+    ///
+    /// 可以是一本书的目录, 也可以是包含多本书的输出目录
+    Import {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CookieAction {
+    /// 从文件导入 cookie (json 或 Netscape cookies.txt)
+    Import {
+        file: String,
+        #[arg(
+            short = 'f',
+            long = "format",
+            help = "文件格式 (json, netscape), 默认自动识别"
+        )]
+        format: Option<cookies::CookieFormat>,
+    },
+    /// 把 cookie 导出到文件
+    Export {
+        file: String,
+        #[arg(
+            short = 'f',
+            long = "format",
+            help = "文件格式 (json, netscape), 默认按扩展名"
+        )]
+        format: Option<cookies::CookieFormat>,
+    },
 }
 
 fn main() -> Result<()> {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
-    rt.block_on(a_main())
+    let result = rt.block_on(a_main());
+    if let Err(e) = &result
+        && e.is::<shutdown::Interrupted>()
+    {
+        eprintln!("{e}");
+        std::process::exit(shutdown::EXIT_INTERRUPTED);
+    }
+    if let Err(e) = &result
+        && e.is::<verify::VerifyFailed>()
+    {
+        eprintln!("{e}");
+        std::process::exit(verify::EXIT_VERIFY_FAILED);
+    }
+    result
 }
 
 async fn a_main() -> Result<()> {
     let args = CliArg::parse();
+    logging::init(&args)?;
 
-    drives::main(args).await?;
+    match &args.command {
+        None => drives::main(args).await?,
+        Some(Command::Cookie(action)) => cookies::run(&args, action)?,
+        Some(Command::Library(action)) => library::run(&args, action)?,
+        Some(Command::Search { query, limit }) => library::search(&args, query, *limit)?,
+        Some(Command::Revisions {
+            book_id,
+            chapter_id,
+            from,
+            to,
+        }) => revisions::run(&args, book_id, chapter_id, *from, *to)?,
+        Some(Command::Catalog { file, json }) => catalog::run(file, *json)?,
+        Some(Command::Reprocess {
+            dir,
+            catalog,
+            book_id,
+            formats,
+            extras,
+            skip_announcements,
+            clean_config,
+            no_clean,
+            convert,
+        }) => {
+            let cleaner = if *no_clean {
+                cleaner::Cleaner::default()
+            } else {
+                cleaner::Cleaner::load(clean_config.as_deref().map(std::path::Path::new))?
+            };
+            let options = export::ExportOptions {
+                cleaner,
+                extras: *extras,
+                skip_announcements: *skip_announcements,
+                converter: convert.map(zhconv::Converter::new),
+                // 插图索引在书目录里, reprocess 自己读
+                images: Default::default(),
+            };
+            reprocess::run(
+                &args,
+                dir,
+                catalog.as_deref(),
+                book_id.as_ref(),
+                formats,
+                &options,
+            )?
+        }
+        Some(Command::Clean {
+            file,
+            config,
+            dry_run,
+        }) => cleaner::run(file, config.as_deref(), *dry_run)?,
+        Some(Command::Verify {
+            dir,
+            tolerance,
+            min_chars,
+            json,
+        }) => {
+            let config = verify::VerifyConfig {
+                tolerance: tolerance / 100.0,
+                min_chars: *min_chars,
+            };
+            verify::run(dir, &config, *json)?
+        }
+    }
 
     Ok(())
 }
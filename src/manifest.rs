@@ -0,0 +1,188 @@
+//! 下载进度清单
+//!
+//! 记录一本书里哪些章节已经保存好了, 中断后再跑会跳过这些章节
+//!
+//! 只有章节文件完整写入之后才会记进来
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    books::{BookChapter, BookInfo, BookVolume, ChapterAccess},
+    fs_util::write_atomic,
+    ids::{BookId, ChapterId, VolumeId},
+    storage::ChapterSink,
+};
+
+/// 一个已保存的章节
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub volume_id: VolumeId,
+    pub title: String,
+    /// 在存储里的位置, 见 [`crate::storage::ChapterKey::relative_path`]
+    pub path: String,
+    /// 目录里给的字数
+    pub length: u32,
+    /// 正文的 sha256, 见 [`crate::revisions::content_hash`]; 老的清单里没有
+    #[serde(default)]
+    pub hash: String,
+    /// 作者改过的旧版本, 从 1 开始编号, 当前版本不在里面
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<Revision>,
+    /// 没订阅, 只看到了试读部分, 存储里没有这一章
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+}
+
+/// 一个被替换掉的旧版本
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revision {
+    pub number: u32,
+    pub hash: String,
+    /// 那时目录里给的字数
+    pub length: u32,
+    /// 被替换的时间, unix 时间戳 (秒)
+    pub replaced_at: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub book_id: BookId,
+    /// 章节 id -> 保存信息
+    pub chapters: BTreeMap<ChapterId, ManifestEntry>,
+}
+
+impl Manifest {
+    pub const FILE_NAME: &str = "manifest.json";
+
+    pub fn new(book_id: impl Into<BookId>) -> Self {
+        Self {
+            book_id: book_id.into(),
+            chapters: BTreeMap::new(),
+        }
+    }
+
+    /// 读取 `book_id` 的清单, 不存在或者是别的书的清单就新建一个
+    pub fn load_for(path: &Path, book_id: &BookId) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new(book_id.clone()));
+        }
+        let manifest: Manifest = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if &manifest.book_id != book_id {
+            warn!(
+                found = %manifest.book_id,
+                expected = %book_id,
+                "清单属于另一本书, 重新开始"
+            );
+            return Ok(Self::new(book_id.clone()));
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// 去掉存储里已经没有了的章节, 让它们重新下载
+    pub fn retain_stored(&mut self, sink: &dyn ChapterSink) -> anyhow::Result<usize> {
+        let before = self.chapters.len();
+        let mut stored = BTreeMap::new();
+        for (id, entry) in std::mem::take(&mut self.chapters) {
+            if entry.locked || sink.exists(&id)? {
+                stored.insert(id, entry);
+            }
+        }
+        self.chapters = stored;
+        Ok(before - self.chapters.len())
+    }
+
+    /// 是否已经保存了正文, 没订阅的章节不算
+    pub fn contains(&self, chapter_id: &ChapterId) -> bool {
+        self.chapters
+            .get(chapter_id)
+            .is_some_and(|entry| !entry.locked)
+    }
+
+    pub fn is_locked(&self, chapter_id: &ChapterId) -> bool {
+        self.chapters
+            .get(chapter_id)
+            .is_some_and(|entry| entry.locked)
+    }
+
+    /// 没订阅的章节
+    pub fn locked(&self) -> impl Iterator<Item = (&ChapterId, &ManifestEntry)> {
+        self.chapters.iter().filter(|(_, entry)| entry.locked)
+    }
+
+    /// 把清单里记的订阅情况写回目录: 没订阅的标成 [`ChapterAccess::Locked`],
+    /// 目录里标着锁但其实已经存下正文的 (后来买了) 改成 [`ChapterAccess::Vip`]
+    pub fn apply_locked(&self, book: &mut BookInfo) {
+        for chapter in book.volumes.iter_mut().flat_map(|v| &mut v.chapters) {
+            if self.is_locked(&chapter.id) {
+                chapter.access = ChapterAccess::Locked;
+            } else if chapter.is_locked() && self.contains(&chapter.id) {
+                chapter.access = ChapterAccess::Vip;
+            }
+        }
+    }
+
+    /// 保存了正文的章节数
+    pub fn stored_count(&self) -> usize {
+        self.chapters.values().filter(|entry| !entry.locked).count()
+    }
+
+    /// 目录里的字数和保存时不一样, 说明作者可能改过, 要重新读一遍
+    pub fn length_changed(&self, chapter: &BookChapter) -> bool {
+        self.chapters
+            .get(&chapter.id)
+            .is_some_and(|entry| entry.length != chapter.length)
+    }
+
+    /// 记下一章已经保存, 以前的旧版本记录保留
+    pub fn record(
+        &mut self,
+        volume: &BookVolume,
+        chapter: &BookChapter,
+        path: impl Into<String>,
+        hash: impl Into<String>,
+    ) {
+        let revisions = self
+            .chapters
+            .remove(&chapter.id)
+            .map(|entry| entry.revisions)
+            .unwrap_or_default();
+        self.chapters.insert(
+            chapter.id.clone(),
+            ManifestEntry {
+                volume_id: volume.id.clone(),
+                title: chapter.title.clone(),
+                path: path.into(),
+                length: chapter.length,
+                hash: hash.into(),
+                revisions,
+                locked: false,
+            },
+        );
+    }
+
+    /// 记下一章没订阅; 以前已经保存过正文的不动
+    pub fn record_locked(&mut self, volume: &BookVolume, chapter: &BookChapter) {
+        if self.contains(&chapter.id) {
+            return;
+        }
+        self.chapters.insert(
+            chapter.id.clone(),
+            ManifestEntry {
+                volume_id: volume.id.clone(),
+                title: chapter.title.clone(),
+                path: String::new(),
+                length: chapter.length,
+                hash: String::new(),
+                revisions: Vec::new(),
+                locked: true,
+            },
+        );
+    }
+}
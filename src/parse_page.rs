@@ -1,13 +1,18 @@
-use scraper::{Html, Selector, selectable::Selectable};
+use scraper::{ElementRef, Html, Selector, selectable::Selectable};
 
-use crate::books::{BookChapter, BookInfo, BookVolume};
+use crate::{
+    books::{BookChapter, BookInfo, BookVolume, ChapterAccess},
+    ids::{BookId, QidianUrl, VolumeId},
+};
 
 pub mod book_info {
     use std::sync::OnceLock;
 
     use regex::Regex;
+    use tracing::warn;
 
     use super::*;
+    use crate::catalog::{CatalogWarning, check_numbering};
 
     /// 非常好 regex, 爱来自 deeepseek v3-0324
     static RELEASE_DATE_COUNT_RE: OnceLock<Regex> = OnceLock::new();
@@ -46,59 +51,262 @@ pub mod book_info {
         Some((time.to_string(), count))
     }
 
-    pub fn parse(html: String) -> BookInfo {
-        let raw_html = Html::parse_fragment(&html);
+    /// 卷头里写的章数, "正文卷·共72章免费"
+    fn declared_chapter_count(header_text: &str) -> Option<usize> {
+        static COUNT_RE: OnceLock<Regex> = OnceLock::new();
+        let re = COUNT_RE.get_or_init(|| Regex::new(r"共\s*(\d+)\s*章").unwrap());
+        re.captures(header_text)?.get(1)?.as_str().parse().ok()
+    }
+
+    /// 章节条目 (`li.chapter-item`) 上的锁 / VIP / 免费标记
+    ///
+    /// 登录后目录只给没订阅的章节挂锁, 所以 VIP 卷里没有标记的章节算能读;
+    /// 卷头都没有的时候就是不知道
+    fn chapter_access(item: Option<ElementRef>, volume_access: ChapterAccess) -> ChapterAccess {
+        static SELECTORS: OnceLock<[(Selector, ChapterAccess); 3]> = OnceLock::new();
+        let selectors = SELECTORS.get_or_init(|| {
+            [
+                (
+                    Selector::parse(
+                        ".chapter-locked, .lock, [class*='icon-lock'], [data-locked='1']",
+                    )
+                    .unwrap(),
+                    ChapterAccess::Locked,
+                ),
+                (
+                    Selector::parse(".vip, .chapter-vip").unwrap(),
+                    ChapterAccess::Vip,
+                ),
+                (
+                    Selector::parse(".free, .chapter-free").unwrap(),
+                    ChapterAccess::Free,
+                ),
+            ]
+        });
+        let Some(item) = item else {
+            return volume_access;
+        };
+        selectors
+            .iter()
+            .find(|(selector, _)| selector.matches(&item) || item.select(selector).next().is_some())
+            .map_or(volume_access, |(_, access)| *access)
+    }
+
+    /// 解析目录, 有问题的地方不会 panic, 而是记成警告
+    ///
+    /// 每一卷是一个 `div.catalog-volume`, 卷头和章节列表都从同一个容器里取, 不会错位
+    pub fn parse_checked(html: &str) -> (BookInfo, Vec<CatalogWarning>) {
+        let raw_html = Html::parse_fragment(html);
+        let volume_selector = Selector::parse("div.catalog-volume").unwrap();
+        let input_selector = Selector::parse("input.input-vol[id]").unwrap();
         let header_selector = Selector::parse("label[for] > div.volume-header").unwrap();
         let name_selector = Selector::parse("h3.volume-name").unwrap();
         let is_free_selector = Selector::parse("span.free").unwrap();
-        let chapter_selector = Selector::parse("ul.volume-chapters").unwrap();
-        let chapter_item_selector = Selector::parse("a.chapter-name").unwrap();
-        let header_items: Vec<_> = raw_html.select(&header_selector).collect();
-        let chapter_items = raw_html.select(&chapter_selector);
+        let chapter_item_selector = Selector::parse("ul.volume-chapters a.chapter-name").unwrap();
 
-        let mut volumes: Vec<BookVolume> = Vec::with_capacity(header_items.len());
+        let mut warnings = Vec::new();
+        let mut volumes: Vec<BookVolume> = Vec::new();
 
-        for volume_header in header_items {
-            let label = volume_header
-                .parent()
+        for (index, container) in raw_html.select(&volume_selector).enumerate() {
+            let header = container.select(&header_selector).next();
+            let label_id = header
+                .and_then(|header| header.parent())
                 .and_then(|n| n.value().as_element())
-                .expect("找不到父级 label 元素");
-
-            let volume_id = label.attr("for").expect("label 缺少 for 属性");
-            let volume_name = volume_header
-                .select(&name_selector)
+                .and_then(|label| label.attr("for"));
+            let input_id = container
+                .select(&input_selector)
                 .next()
+                .and_then(|input| input.attr("id"));
+            let volume_id = label_id
+                .or(input_id)
+                .map(VolumeId::new)
+                .unwrap_or_else(|| VolumeId::new(format!("vol-{index}")));
+            let name = header.and_then(|header| header.select(&name_selector).next());
+            let volume_name = name
                 .and_then(|h3| h3.text().next())
-                .map(|s| s.trim())
-                .expect("找不到卷名");
-            let is_free = volume_header.select(&is_free_selector).next().is_some();
-            let volume = BookVolume {
-                title: volume_name.to_string(),
-                is_vip: !is_free,
-                id: volume_id.to_string(),
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default();
+            if header.is_none() || volume_name.is_empty() {
+                warnings.push(CatalogWarning::MissingVolumeHeader {
+                    index,
+                    volume_id: volume_id.clone(),
+                });
+            }
+            let is_free =
+                header.is_some_and(|header| header.select(&is_free_selector).next().is_some());
+
+            let volume_access = match (header, is_free) {
+                (None, _) => ChapterAccess::Unknown,
+                (Some(_), true) => ChapterAccess::Free,
+                (Some(_), false) => ChapterAccess::Vip,
+            };
+
+            let mut volume = BookVolume {
+                title: volume_name,
+                is_vip: header.is_some() && !is_free,
+                id: volume_id,
                 chapters: Vec::new(),
             };
+            for inner_chapter in container.select(&chapter_item_selector) {
+                let title: String = inner_chapter.text().collect::<String>().trim().to_string();
+                let Some(href) = inner_chapter.attr("href") else {
+                    warnings.push(CatalogWarning::BadChapter {
+                        volume_id: volume.id.clone(),
+                        title,
+                        reason: "没有链接".to_string(),
+                    });
+                    continue;
+                };
+                let infos = inner_chapter.attr("title").unwrap_or_default();
+                let (date, len) = analyze_chapter_name(infos).unwrap_or_else(|| {
+                    warnings.push(CatalogWarning::BadChapter {
+                        volume_id: volume.id.clone(),
+                        title: title.clone(),
+                        reason: format!("看不懂发布时间和字数: {infos:?}"),
+                    });
+                    (String::new(), 0)
+                });
+                let release_date = date.parse().ok();
+                if release_date.is_none() && !date.is_empty() {
+                    warnings.push(CatalogWarning::BadChapter {
+                        volume_id: volume.id.clone(),
+                        title: title.clone(),
+                        reason: format!("发布时间不对: {date:?}"),
+                    });
+                }
+                match BookChapter::new_from_html(href, release_date, title.clone(), len) {
+                    Some(mut chapter) => {
+                        let item = inner_chapter
+                            .parent()
+                            .and_then(ElementRef::wrap)
+                            .filter(|li| li.value().name() == "li");
+                        chapter.access = chapter_access(item, volume_access);
+                        volume.chapters.push(chapter);
+                    }
+                    None => warnings.push(CatalogWarning::BadChapter {
+                        volume_id: volume.id.clone(),
+                        title,
+                        reason: format!("看不懂章节链接: {href:?}"),
+                    }),
+                }
+            }
+
+            let declared =
+                name.and_then(|h3| declared_chapter_count(&h3.text().collect::<String>()));
+            if let Some(declared) = declared
+                && declared != volume.chapters.len()
+            {
+                warnings.push(CatalogWarning::ChapterCountMismatch {
+                    volume_id: volume.id.clone(),
+                    volume_title: volume.title.clone(),
+                    declared,
+                    found: volume.chapters.len(),
+                });
+            }
+            warnings.extend(check_numbering(&volume));
             volumes.push(volume);
         }
 
-        for (index, chatper) in chapter_items.enumerate() {
-            let volume = volumes.get_mut(index).unwrap();
-            let inner_chapters = chatper.select(&chapter_item_selector);
-
-            for inner_chapter in inner_chapters {
-                let infos = inner_chapter.attr("title").expect("章节未找到标题");
-                let href = inner_chapter.attr("href").expect("章节未找到链接");
-                let title: String = inner_chapter.text().collect();
-                let (date, len) = analyze_chapter_name(infos).expect("解析标题错误");
-                let chapter = BookChapter::new_from_html(href, date, title, len);
-                volume.chapters.push(chapter);
-            }
+        if volumes.is_empty() {
+            warnings.push(CatalogWarning::NoVolumes);
+        }
+        let link_selector = Selector::parse("a.chapter-name").unwrap();
+        let outside = raw_html
+            .select(&link_selector)
+            .filter(|link| {
+                !link
+                    .ancestors()
+                    .filter_map(ElementRef::wrap)
+                    .any(|element| volume_selector.matches(&element))
+            })
+            .count();
+        if outside > 0 {
+            warnings.push(CatalogWarning::ChaptersOutsideVolumes { count: outside });
         }
 
-        BookInfo {
-            id: "book_id".to_string(),
+        let book = BookInfo {
+            id: BookId::default(),
+            title: String::new(),
             volumes,
+        };
+        (book, warnings)
+    }
+
+    /// 解析目录, 警告直接打到日志里
+    pub fn parse(html: String) -> BookInfo {
+        let (book, warnings) = parse_checked(&html);
+        for warning in &warnings {
+            warn!("目录有问题: {warning}");
         }
+        book
+    }
+
+    /// 解析保存下来的页面, 可以是整个书页, 也可以只是 `#allCatalog` 里面的内容
+    ///
+    /// 书 id 和书名尽量从页面里找, 找不到就是空字符串
+    pub fn parse_document(html: &str) -> (BookInfo, Vec<CatalogWarning>) {
+        let document = Html::parse_document(html);
+        let catalog_selector = Selector::parse("#allCatalog").unwrap();
+        let catalog = document
+            .select(&catalog_selector)
+            .next()
+            .map(|catalog| catalog.inner_html())
+            .unwrap_or_else(|| html.to_string());
+        let (mut book, warnings) = parse_checked(&catalog);
+
+        let meta = |property: &str| {
+            let selector = Selector::parse(&format!("meta[property='{property}']")).unwrap();
+            document
+                .select(&selector)
+                .next()
+                .and_then(|meta| meta.attr("content"))
+                .map(|content| content.trim().to_string())
+                .filter(|content| !content.is_empty())
+        };
+        let first_chapter = book
+            .volumes
+            .iter()
+            .flat_map(|volume| volume.chapters.first())
+            .next();
+
+        book.id = meta("og:url")
+            .and_then(|url| QidianUrl::parse(&url))
+            .or_else(|| first_chapter.and_then(BookChapter::qidian_url))
+            .map(|url| url.book_id().clone())
+            .unwrap_or_default();
+
+        let book_name_selector = Selector::parse("#bookName, h1.book-name").unwrap();
+        let title_selector = Selector::parse("title").unwrap();
+        let chapter_link_selector = Selector::parse("a.chapter-name[alt]").unwrap();
+        book.title = meta("og:novel:book_name")
+            .or_else(|| {
+                document
+                    .select(&book_name_selector)
+                    .next()
+                    .map(|h1| h1.text().collect::<String>().trim().to_string())
+                    .filter(|name| !name.is_empty())
+            })
+            .or_else(|| {
+                // 《书名》小说在线阅读_起点中文网
+                let title: String = document.select(&title_selector).next()?.text().collect();
+                let (_, rest) = title.split_once('《')?;
+                let (name, _) = rest.split_once('》')?;
+                Some(name.trim().to_string())
+            })
+            .or_else(|| {
+                // 只有目录的时候从章节链接的 alt 里取: "围棋：我和AI五五开 1.应杰在线阅读"
+                let link = document.select(&chapter_link_selector).next()?;
+                let chapter_title: String = link.text().collect();
+                let name = link
+                    .attr("alt")?
+                    .trim()
+                    .trim_end_matches("在线阅读")
+                    .strip_suffix(chapter_title.trim())?
+                    .trim();
+                (!name.is_empty()).then(|| name.to_string())
+            })
+            .unwrap_or_default();
+        (book, warnings)
     }
 
     #[cfg(test)]
@@ -185,5 +393,983 @@ pub mod book_info {
             let book = book_info::parse(TEST_HTML.to_string());
             assert_eq!(book.volumes.len(), 6);
         }
+
+        #[test]
+        fn test_parse_volumes() {
+            let (book, warnings) = parse_checked(TEST_HTML);
+            assert!(warnings.is_empty(), "{warnings:?}");
+            let counts: Vec<_> = book.volumes.iter().map(|v| v.chapters.len()).collect();
+            assert_eq!(counts, vec![72, 329, 40, 20, 23, 13]);
+            assert_eq!(book.volumes[0].title, "正文卷");
+            assert!(!book.volumes[0].is_vip);
+            assert!(book.volumes[1].is_vip);
+            assert_eq!(book.volumes[1].chapters[0].title, "72.Boss竟是我自己");
+        }
+
+        #[test]
+        fn test_parse_broken_volumes() {
+            // 第一卷没有卷头, 第二卷声明 3 章只有 1 章, 章节标题看不懂
+            let html = r#"
+                <div class="catalog-volume">
+                    <input class="input-vol" type="checkbox" id="vol1" />
+                    <ul class="volume-chapters">
+                        <li><a class="chapter-name" href="//www.qidian.com/chapter/1/11/"
+                            title="书 1.一 首发时间：2023-04-03 10:19:10 章节字数：100">1.一</a></li>
+                    </ul>
+                </div>
+                <div class="catalog-volume">
+                    <input class="input-vol" type="checkbox" id="vol2" />
+                    <label for="vol2"><div class="volume-header">
+                        <h3 class="volume-name">第二卷<span class="dot">·</span>共3章<span class="vip">VIP</span></h3>
+                    </div></label>
+                    <ul class="volume-chapters">
+                        <li><a class="chapter-name" href="//www.qidian.com/chapter/1/22/" title="乱码">2.二</a></li>
+                    </ul>
+                </div>
+            "#;
+            let (book, warnings) = parse_checked(html);
+            assert_eq!(book.volumes.len(), 2);
+            assert_eq!(book.volumes[0].id, "vol1");
+            assert_eq!(book.volumes[0].chapters[0].id, "11");
+            assert_eq!(book.volumes[1].chapters[0].id, "22");
+            assert_eq!(book.volumes[1].chapters[0].length, 0);
+            assert_eq!(
+                warnings,
+                vec![
+                    CatalogWarning::MissingVolumeHeader {
+                        index: 0,
+                        volume_id: VolumeId::new("vol1")
+                    },
+                    CatalogWarning::BadChapter {
+                        volume_id: VolumeId::new("vol2"),
+                        title: "2.二".to_string(),
+                        reason: "看不懂发布时间和字数: \"乱码\"".to_string()
+                    },
+                    CatalogWarning::ChapterCountMismatch {
+                        volume_id: VolumeId::new("vol2"),
+                        volume_title: "第二卷".to_string(),
+                        declared: 3,
+                        found: 1
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_parse_chapter_access() {
+            let (book, _) = parse_checked(TEST_HTML);
+            // 第一卷免费, 后面都是 VIP 卷, 目录里没有锁
+            assert!(
+                book.volumes[0]
+                    .chapters
+                    .iter()
+                    .all(|chapter| chapter.access == ChapterAccess::Free)
+            );
+            let summary = book.access_summary();
+            assert_eq!((summary.free, summary.vip, summary.locked), (72, 425, 0));
+
+            let html = r#"
+                <div class="catalog-volume">
+                    <label for="vol2"><div class="volume-header">
+                        <h3 class="volume-name">第二卷<span class="dot">·</span>共4章<span class="vip">VIP</span></h3>
+                    </div></label>
+                    <ul class="volume-chapters">
+                        <li class="chapter-item"><a class="chapter-name" href="//www.qidian.com/chapter/1/21/"
+                            title="书 1.一 首发时间：2023-04-03 10:19:10 章节字数：100">1.一</a></li>
+                        <li class="chapter-item"><a class="chapter-name" href="//www.qidian.com/chapter/1/22/"
+                            title="书 2.二 首发时间：2023-04-04 10:19:10 章节字数：100">2.二</a><em class="iconfont chapter-locked"></em></li>
+                        <li class="chapter-item chapter-locked"><a class="chapter-name" href="//www.qidian.com/chapter/1/23/"
+                            title="书 3.三 首发时间：2023-04-05 10:19:10 章节字数：100">3.三</a></li>
+                        <li class="chapter-item"><a class="chapter-name" href="//www.qidian.com/chapter/1/24/"
+                            title="书 4.四 首发时间：2023-04-06 10:19:10 章节字数：100">4.四</a><span class="free">免费</span></li>
+                    </ul>
+                </div>
+            "#;
+            let (book, warnings) = parse_checked(html);
+            assert!(warnings.is_empty(), "{warnings:?}");
+            let access: Vec<_> = book.volumes[0]
+                .chapters
+                .iter()
+                .map(|chapter| chapter.access)
+                .collect();
+            assert_eq!(
+                access,
+                [
+                    ChapterAccess::Vip,
+                    ChapterAccess::Locked,
+                    ChapterAccess::Locked,
+                    ChapterAccess::Free
+                ]
+            );
+            assert_eq!(
+                book.locked_chapters()
+                    .map(|c| c.id.as_str())
+                    .collect::<Vec<_>>(),
+                ["22", "23"]
+            );
+
+            // 没有卷头的卷不知道
+            let (book, _) = parse_checked(
+                r#"<div class="catalog-volume"><ul class="volume-chapters">
+                <li><a class="chapter-name" href="//www.qidian.com/chapter/1/11/">1.一</a></li>
+                </ul></div>"#,
+            );
+            assert_eq!(book.volumes[0].chapters[0].access, ChapterAccess::Unknown);
+        }
+
+        #[test]
+        fn test_parse_without_volumes() {
+            let (book, warnings) = parse_checked("<div>目录加载失败</div>");
+            assert!(book.volumes.is_empty());
+            assert_eq!(warnings, vec![CatalogWarning::NoVolumes]);
+
+            // 章节链接在卷外面
+            let (book, warnings) = parse_checked(
+                r#"<div class="catalog-volume"><ul class="volume-chapters">
+                <li><a class="chapter-name" href="//www.qidian.com/chapter/1/11/"
+                    title="书 1.一 首发时间：2023-04-03 10:19:10 章节字数：100">1.一</a></li>
+                </ul></div>
+                <ul><li><a class="chapter-name" href="//www.qidian.com/chapter/1/12/">2.二</a></li></ul>"#,
+            );
+            assert_eq!(book.volumes[0].chapters.len(), 1);
+            assert_eq!(
+                warnings,
+                vec![
+                    CatalogWarning::MissingVolumeHeader {
+                        index: 0,
+                        volume_id: VolumeId::new("vol-0")
+                    },
+                    CatalogWarning::ChaptersOutsideVolumes { count: 1 },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_parse_document() {
+            // 只有目录
+            let (book, warnings) = parse_document(TEST_HTML);
+            assert!(warnings.is_empty(), "{warnings:?}");
+            assert_eq!(book.volumes.len(), 6);
+            assert_eq!(book.id, "1036741406");
+            assert_eq!(book.title, "围棋：我和AI五五开");
+
+            // 整个书页, meta 优先
+            let page = format!(
+                r#"<html><head><title>《别的名字》小说在线阅读_起点中文网</title>
+                <meta property="og:url" content="https://www.qidian.com/book/1036741406/">
+                <meta property="og:novel:book_name" content="围棋：我和AI五五开">
+                </head><body><div id="allCatalog">{TEST_HTML}</div></body></html>"#
+            );
+            let (book, _) = parse_document(&page);
+            assert_eq!(book.volumes.len(), 6);
+            assert_eq!(book.id, "1036741406");
+            assert_eq!(book.title, "围棋：我和AI五五开");
+
+            let page = "<html><head><title>《书名》小说在线阅读</title></head><body></body></html>";
+            let (book, _) = parse_document(page);
+            assert_eq!(book.title, "书名");
+            assert_eq!(book.id, "");
+            assert!(book.volumes.is_empty());
+        }
+    }
+}
+
+pub mod reader_page {
+    use super::*;
+
+    /// 阅读页的类型
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PageKind {
+        /// 正常的章节内容
+        Reader,
+        /// 滑块之类的人机验证
+        Challenge,
+        /// 要求登录
+        LoginWall,
+        /// 没有订阅的 VIP 章节
+        Unpurchased,
+        /// 404 / 服务器错误 / 根本没有正文
+        Error,
+    }
+
+    /// 正文少于这么多字时才按文字提示判断, 免得小说内容里恰好出现 "验证码" 之类的词
+    const SHORT_MAIN_CHARS: usize = 500;
+
+    /// 验证页的 url 特征
+    const CHALLENGE_URL_MARKERS: &[&str] = &["captcha", "/verify", "waf"];
+    /// 验证组件的元素 (腾讯滑块, 阿里滑块等)
+    const CHALLENGE_SELECTORS: &str = "#tcaptcha_iframe, #tcaptcha_transform, iframe[src*='captcha'], #nc_1_wrapper, .nc-container, #captcha";
+    const CHALLENGE_TEXTS: &[&str] = &[
+        "安全验证",
+        "拖动滑块",
+        "请完成验证",
+        "人机验证",
+        "访问过于频繁",
+    ];
+
+    /// 被重定向去登录时 url 里会出现的东西
+    const LOGIN_URL_MARKERS: &[&str] = &["passport.qidian.com", "passport.yuewen.com", "/login"];
+    /// 阅读页要求登录时会出现的提示
+    const LOGIN_TEXTS: &[&str] = &["登录后继续阅读", "请先登录", "登录即可阅读"];
+
+    /// 没订阅的 VIP 章节会出现的提示
+    const UNPURCHASED_TEXTS: &[&str] = &["订阅本章", "购买本章", "订阅后继续阅读", "本章为VIP章节"];
+    /// 试读页正文下面的订阅框和遮罩
+    const UNPURCHASED_SELECTORS: &str =
+        "#j_chapterPay, .chapter-pay, .vip-limit-wrap, .lock-mask, .subscribe-btn, .buy-chapter";
+    /// 正文不到目录字数的这个比例, 又有订阅提示时, 算只拿到了试读部分
+    const TRUNCATED_RATIO: f64 = 0.8;
+
+    const ERROR_TEXTS: &[&str] = &[
+        "页面不存在",
+        "404 Not Found",
+        "服务器繁忙",
+        "网络异常",
+        "出错了",
+    ];
+
+    /// 根据 url 和整页 html 判断当前是什么页面
+    pub fn classify(url: &str, html: &str) -> PageKind {
+        let url = url.to_lowercase();
+        if CHALLENGE_URL_MARKERS.iter().any(|m| url.contains(m)) {
+            return PageKind::Challenge;
+        }
+        if LOGIN_URL_MARKERS.iter().any(|m| url.contains(m)) {
+            return PageKind::LoginWall;
+        }
+
+        let document = Html::parse_document(html);
+        let challenge_selector = Selector::parse(CHALLENGE_SELECTORS).unwrap();
+        if document.select(&challenge_selector).next().is_some() {
+            return PageKind::Challenge;
+        }
+        let unpurchased_selector = Selector::parse(UNPURCHASED_SELECTORS).unwrap();
+        if document.select(&unpurchased_selector).next().is_some() {
+            return PageKind::Unpurchased;
+        }
+
+        let main_selector = Selector::parse("main").unwrap();
+        let main_text: Option<String> = document
+            .select(&main_selector)
+            .next()
+            .map(|main| main.text().collect());
+        let main_len = main_text
+            .as_deref()
+            .map(|text| text.trim().chars().count())
+            .unwrap_or_default();
+
+        if main_len < SHORT_MAIN_CHARS {
+            let body_text: String = document.root_element().text().collect();
+            let has = |texts: &[&str]| texts.iter().any(|t| body_text.contains(t));
+            if has(CHALLENGE_TEXTS) {
+                return PageKind::Challenge;
+            }
+            if has(LOGIN_TEXTS) {
+                return PageKind::LoginWall;
+            }
+            if has(UNPURCHASED_TEXTS) {
+                return PageKind::Unpurchased;
+            }
+            if has(ERROR_TEXTS) {
+                return PageKind::Error;
+            }
+        }
+
+        if main_text.is_none() || main_len == 0 {
+            return PageKind::Error;
+        }
+        PageKind::Reader
+    }
+
+    /// 保存下来的 `<main>` 是不是只有试读部分
+    ///
+    /// 试读页的正文可能比较长, [`classify`] 按字数判断不出来, 这里再和目录里的字数比一下
+    pub fn is_truncated(html: &str, expected_len: u32) -> bool {
+        let document = Html::parse_fragment(html);
+        let unpurchased_selector = Selector::parse(UNPURCHASED_SELECTORS).unwrap();
+        if document.select(&unpurchased_selector).next().is_some() {
+            return true;
+        }
+        let text: String = document.root_element().text().collect();
+        if !UNPURCHASED_TEXTS.iter().any(|t| text.contains(t)) {
+            return false;
+        }
+        let count = chapter_content::parse(html).char_count();
+        (count as f64) < expected_len as f64 * TRUNCATED_RATIO
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        const CHAPTER_URL: &str = "https://www.qidian.com/chapter/1036741406/748679604/";
+
+        fn reader_html(body: &str) -> String {
+            format!("<html><body><div id=\"reader\"><main>{body}</main></div></body></html>")
+        }
+
+        #[test]
+        fn test_classify() {
+            let long_text = "正文内容。".repeat(200);
+            let cases = [
+                (CHAPTER_URL, reader_html(&long_text), PageKind::Reader),
+                (
+                    CHAPTER_URL,
+                    // 正文够长时, 里面出现的词不算数
+                    reader_html(&format!("{long_text}他说: 请完成验证")),
+                    PageKind::Reader,
+                ),
+                (
+                    CHAPTER_URL,
+                    "<html><body><iframe id=\"tcaptcha_iframe\"></iframe></body></html>"
+                        .to_string(),
+                    PageKind::Challenge,
+                ),
+                (
+                    CHAPTER_URL,
+                    "<html><body><p>请拖动滑块完成安全验证</p></body></html>".to_string(),
+                    PageKind::Challenge,
+                ),
+                (
+                    "https://passport.qidian.com/?returnurl=x",
+                    "<html></html>".to_string(),
+                    PageKind::LoginWall,
+                ),
+                (
+                    CHAPTER_URL,
+                    reader_html("<p>第一段</p><p>登录后继续阅读</p>"),
+                    PageKind::LoginWall,
+                ),
+                (
+                    CHAPTER_URL,
+                    reader_html("<p>第一段</p><button>订阅本章</button>"),
+                    PageKind::Unpurchased,
+                ),
+                (
+                    CHAPTER_URL,
+                    "<html><body><h1>页面不存在</h1></body></html>".to_string(),
+                    PageKind::Error,
+                ),
+                (CHAPTER_URL, reader_html(""), PageKind::Error),
+            ];
+            for (url, html, expected) in cases {
+                assert_eq!(classify(url, &html), expected, "{html}");
+            }
+        }
+
+        #[test]
+        fn test_is_truncated() {
+            let preview = format!(
+                "<h1>100.试读</h1><p>{}</p><p>本章为VIP章节, 订阅后继续阅读</p>",
+                "试读内容".repeat(150)
+            );
+            // 试读部分够长, 按页面判断不出来
+            assert_eq!(
+                classify(CHAPTER_URL, &reader_html(&preview)),
+                PageKind::Reader
+            );
+            assert!(is_truncated(&preview, 3000));
+            // 字数够了, 正文里提到订阅不算
+            assert!(!is_truncated(&preview, 600));
+            assert!(!is_truncated("<p>第一段</p>", 3000));
+
+            let paywall = "<p>第一段</p><div class=\"vip-limit-wrap\"><a>立即订阅</a></div>";
+            assert!(is_truncated(paywall, 0));
+            assert_eq!(
+                classify(CHAPTER_URL, &reader_html(paywall)),
+                PageKind::Unpurchased
+            );
+        }
+    }
+}
+
+pub mod site_header {
+    use super::*;
+
+    /// 登录后页头里显示昵称的元素
+    const NICKNAME_SELECTORS: &str = "#nav-user-name, .login-user .nickname, .user-name";
+    /// 登录后页头里的头像
+    const AVATAR_SELECTORS: &str = ".login-user img, .user-avatar img, #user-avatar";
+    /// 没登录时页头里的登录按钮, 登录后还在页面里, 只是藏起来了
+    const LOGIN_BUTTON_SELECTOR: &str = "#login-btn";
+
+    /// 登录状态
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum LoginState {
+        /// 已登录, 能找到昵称就带上
+        LoggedIn { nickname: Option<String> },
+        /// 页面上显示着登录按钮
+        LoggedOut,
+        /// 两种标志都没找到 (比如不在起点的普通页面上)
+        Unknown,
+    }
+
+    impl LoginState {
+        pub fn is_logged_in(&self) -> bool {
+            matches!(self, LoginState::LoggedIn { .. })
+        }
+    }
+
+    /// 元素自己或者外层被 `hidden`, `display: none` 或者 `hidden` 类藏起来了
+    fn is_hidden(element: ElementRef) -> bool {
+        std::iter::once(element)
+            .chain(element.ancestors().filter_map(ElementRef::wrap))
+            .any(|e| {
+                let value = e.value();
+                let style = value
+                    .attr("style")
+                    .unwrap_or_default()
+                    .replace(' ', "")
+                    .to_lowercase();
+                value.attr("hidden").is_some()
+                    || style.contains("display:none")
+                    || style.contains("visibility:hidden")
+                    || value.classes().any(|c| c == "hidden" || c == "hide")
+            })
+    }
+
+    /// 根据起点普通页面 (首页, 书页) 的整页 html 判断登录状态
+    pub fn login_state(html: &str) -> LoginState {
+        let document = Html::parse_document(html);
+        let login_button = Selector::parse(LOGIN_BUTTON_SELECTOR).unwrap();
+        if document.select(&login_button).any(|e| !is_hidden(e)) {
+            return LoginState::LoggedOut;
+        }
+        let nickname_selector = Selector::parse(NICKNAME_SELECTORS).unwrap();
+        if let Some(element) = document.select(&nickname_selector).next() {
+            let nickname = element.text().collect::<String>().trim().to_string();
+            return LoginState::LoggedIn {
+                nickname: (!nickname.is_empty()).then_some(nickname),
+            };
+        }
+        let avatar_selector = Selector::parse(AVATAR_SELECTORS).unwrap();
+        if document.select(&avatar_selector).next().is_some() {
+            return LoginState::LoggedIn { nickname: None };
+        }
+        LoginState::Unknown
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_login_state() {
+            let logged_in = r#"<html><body><div id="pin-nav"><div class="login-box">
+                <a id="login-btn" class="hidden" href="javascript:">登录</a>
+                <div class="login-user"><img src="//facepic.qidian.com/a.jpg"><span class="nickname"> 书友20230101 </span></div>
+                </div></div></body></html>"#;
+            assert_eq!(
+                login_state(logged_in),
+                LoginState::LoggedIn {
+                    nickname: Some("书友20230101".to_string())
+                }
+            );
+            // 昵称还没加载出来, 只有头像
+            let avatar_only = r#"<html><body><a id="login-btn" style="display: none">登录</a>
+                <div class="user-avatar"><img src="//facepic.qidian.com/a.jpg"></div></body></html>"#;
+            assert_eq!(
+                login_state(avatar_only),
+                LoginState::LoggedIn { nickname: None }
+            );
+
+            let logged_out = r#"<html><body><div class="login-box">
+                <a id="login-btn" href="javascript:">登录</a><a id="reg-btn">注册</a>
+                </div></body></html>"#;
+            assert_eq!(login_state(logged_out), LoginState::LoggedOut);
+
+            let unknown = "<html><body><main><p>正文</p></main></body></html>";
+            assert_eq!(login_state(unknown), LoginState::Unknown);
+        }
+    }
+}
+
+pub mod chapter_content {
+    use std::{fmt, str::FromStr};
+
+    use super::*;
+    use crate::books::ChapterNumbering;
+
+    /// 正文后面附带的东西
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SectionKind {
+        /// 作者有话说, PS
+        AuthorNote,
+        /// 求月票, 求订阅
+        Appeal,
+        /// 章节里夹带的公告
+        Announcement,
+    }
+
+    impl fmt::Display for SectionKind {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                SectionKind::AuthorNote => "作者的话",
+                SectionKind::Appeal => "求票",
+                SectionKind::Announcement => "公告",
+            })
+        }
+    }
+
+    /// 从正文里拆出来的一段, 段落里包括开头的 "作者有话说" 之类的标记
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ExtraSection {
+        pub kind: SectionKind,
+        pub paragraphs: Vec<String>,
+    }
+
+    /// 正文里的插图
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ChapterImage {
+        /// 图片地址, 已经补全成 https 开头
+        pub url: String,
+        pub alt: String,
+        /// 放在第几段后面, 0 是正文最前面
+        pub after: usize,
+    }
+
+    /// 一章的正文
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct ChapterContent {
+        /// 页面里的章节标题
+        pub title: Option<String>,
+        /// 正文段落, 已经去掉首尾空白, 不包括 [`ChapterContent::sections`]
+        pub paragraphs: Vec<String>,
+        /// 作者的话之类的, 按在页面里的顺序
+        pub sections: Vec<ExtraSection>,
+        /// 插图, 按在页面里的顺序
+        pub images: Vec<ChapterImage>,
+    }
+
+    impl ChapterContent {
+        /// 纯文本, 一段一行
+        pub fn text(&self) -> String {
+            self.paragraphs.join("\n")
+        }
+
+        /// 正文和附带的段落, 和拆开之前一样
+        pub fn all_paragraphs(&self) -> impl Iterator<Item = &String> {
+            self.paragraphs
+                .iter()
+                .chain(self.sections.iter().flat_map(|section| &section.paragraphs))
+        }
+
+        /// 包括作者的话在内的全部文字
+        pub fn full_text(&self) -> String {
+            self.all_paragraphs()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        /// 正文字数 (不算空白和作者的话)
+        pub fn char_count(&self) -> usize {
+            self.paragraphs
+                .iter()
+                .map(|p| p.chars().filter(|c| !c.is_whitespace()).count())
+                .sum()
+        }
+    }
+
+    /// 页面里单独放作者的话的元素
+    const AUTHOR_NOTE_SELECTORS: &str = ".author-say, .author-words, .author_say, #authorSay";
+
+    const AUTHOR_NOTE_MARKERS: &[&str] = &[
+        "作者有话说",
+        "作者有话要说",
+        "作者的话",
+        "作者君",
+        "PS",
+        "P.S",
+        "ps",
+        "Ps",
+    ];
+    const APPEAL_MARKERS: &[&str] = &[
+        "求月票",
+        "求推荐票",
+        "求推荐",
+        "求订阅",
+        "求收藏",
+        "求打赏",
+        "求追读",
+    ];
+    const ANNOUNCEMENT_MARKERS: &[&str] = &["【公告】", "公告：", "公告:", "通知：", "通知:"];
+
+    /// 这一段是不是一个附带部分的开头
+    fn section_start(paragraph: &str) -> Option<SectionKind> {
+        let text = paragraph.trim_start_matches(['(', '（', '【', '[', '　', ' ']);
+        if AUTHOR_NOTE_MARKERS.iter().any(|m| {
+            text.strip_prefix(m).is_some_and(|rest| {
+                // "PS" 后面得跟标点, 免得 "PSP", "PS4" 之类的
+                !m.is_ascii()
+                    || rest.is_empty()
+                    || rest.starts_with([':', '：', '.', ',', '，', ' ', '　', ')', '）'])
+            })
+        }) {
+            return Some(SectionKind::AuthorNote);
+        }
+        if APPEAL_MARKERS.iter().any(|m| text.starts_with(m)) {
+            return Some(SectionKind::Appeal);
+        }
+        if ANNOUNCEMENT_MARKERS
+            .iter()
+            .any(|m| paragraph.starts_with(m))
+        {
+            return Some(SectionKind::Announcement);
+        }
+        None
+    }
+
+    /// 附带部分只在章末: 标记要在最后这么多段里
+    const TRAILING_PARAGRAPHS: usize = 6;
+    /// 或者从标记到章末加起来不超过这么多字
+    const TRAILING_CHARS: usize = 300;
+
+    /// 从章末那块里的第一个标记开始, 后面的段落都算附带部分; 第一段不算, 免得整章都被拆走.
+    /// 章节中间的 "公告：宗门大比…" 之类是正文, 不拆
+    fn split_sections(mut paragraphs: Vec<String>) -> (Vec<String>, Vec<ExtraSection>) {
+        let trailing = |i: usize| {
+            paragraphs.len() - i <= TRAILING_PARAGRAPHS
+                || paragraphs[i..]
+                    .iter()
+                    .map(|p| p.chars().filter(|c| !c.is_whitespace()).count())
+                    .sum::<usize>()
+                    <= TRAILING_CHARS
+        };
+        let Some(first) =
+            (1..paragraphs.len()).find(|&i| trailing(i) && section_start(&paragraphs[i]).is_some())
+        else {
+            return (paragraphs, Vec::new());
+        };
+        let mut sections: Vec<ExtraSection> = Vec::new();
+        for paragraph in paragraphs.split_off(first) {
+            match (section_start(&paragraph), sections.last_mut()) {
+                (Some(kind), Some(last)) if last.kind == kind => last.paragraphs.push(paragraph),
+                (Some(kind), _) => sections.push(ExtraSection {
+                    kind,
+                    paragraphs: vec![paragraph],
+                }),
+                (None, Some(last)) => last.paragraphs.push(paragraph),
+                (None, None) => unreachable!("第一段一定是标记"),
+            }
+        }
+        (paragraphs, sections)
+    }
+
+    fn text_of(element: ElementRef) -> String {
+        element.text().collect::<String>().trim().to_string()
+    }
+
+    /// 懒加载的图片真正的地址在 `data-*` 里, `src` 只是占位图
+    const IMAGE_SOURCE_ATTRS: &[&str] = &["data-original", "data-src", "src"];
+
+    /// 补全 `//img.qidian.com/..` 和 `/..` 这样的地址, 内嵌的 `data:` 图片不算
+    fn image_url(img: ElementRef) -> Option<String> {
+        let src = IMAGE_SOURCE_ATTRS
+            .iter()
+            .filter_map(|attr| img.value().attr(attr))
+            .map(str::trim)
+            .find(|src| !src.is_empty())?;
+        if src.starts_with("data:") {
+            None
+        } else if src.starts_with("//") {
+            Some(format!("https:{src}"))
+        } else if src.starts_with('/') {
+            Some(format!("https://www.qidian.com{src}"))
+        } else {
+            Some(src.to_string())
+        }
+    }
+
+    /// 解析保存下来的 `<main>` html
+    pub fn parse(html: &str) -> ChapterContent {
+        let document = Html::parse_fragment(html);
+        let title_selector = Selector::parse("h1").unwrap();
+        let paragraph_selector = Selector::parse("p").unwrap();
+        let content_selector = Selector::parse("p, img").unwrap();
+        let author_selector = Selector::parse(AUTHOR_NOTE_SELECTORS).unwrap();
+
+        let title = document
+            .select(&title_selector)
+            .next()
+            .map(text_of)
+            .filter(|title| !title.is_empty());
+
+        let in_author_note = |p: &ElementRef| {
+            p.ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|element| author_selector.matches(&element))
+        };
+        // 段落和图片按页面里的顺序一起过, 好记下图片在第几段后面
+        let mut paragraphs: Vec<String> = Vec::new();
+        let mut images = Vec::new();
+        for element in document
+            .select(&content_selector)
+            .filter(|e| !in_author_note(e))
+        {
+            if element.value().name() == "img" {
+                if let Some(url) = image_url(element) {
+                    images.push(ChapterImage {
+                        url,
+                        alt: element.value().attr("alt").unwrap_or_default().to_string(),
+                        after: paragraphs.len(),
+                    });
+                }
+                continue;
+            }
+            let text = text_of(element);
+            if !text.is_empty() {
+                paragraphs.push(text);
+            }
+        }
+
+        if paragraphs.is_empty() {
+            // 没有 <p> 的时候按行拆
+            paragraphs = document
+                .root_element()
+                .text()
+                .flat_map(|text| text.lines())
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty() && Some(line) != title.as_ref())
+                .collect();
+        }
+
+        let (paragraphs, mut sections) = split_sections(paragraphs);
+        // 拆到作者的话里去的段落后面的图片, 放到正文最后
+        for image in &mut images {
+            image.after = image.after.min(paragraphs.len());
+        }
+        for note in document.select(&author_selector) {
+            let mut lines: Vec<String> = note
+                .select(&paragraph_selector)
+                .map(text_of)
+                .filter(|p| !p.is_empty())
+                .collect();
+            if lines.is_empty() {
+                lines = note
+                    .text()
+                    .flat_map(|text| text.lines())
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect();
+            }
+            if !lines.is_empty() {
+                sections.push(ExtraSection {
+                    kind: SectionKind::AuthorNote,
+                    paragraphs: lines,
+                });
+            }
+        }
+
+        ChapterContent {
+            title,
+            paragraphs,
+            sections,
+            images,
+        }
+    }
+
+    /// 整章是什么
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ChapterKind {
+        Story,
+        /// 请假条, 上架感言之类的
+        Announcement,
+    }
+
+    const ANNOUNCEMENT_TITLES: &[&str] = &[
+        "请假", "感言", "上架", "公告", "通知", "推书", "新书", "停更", "断更", "说明",
+    ];
+    /// 标题里有这些词的, 正文再长也是公告 (上架感言常常写得很长)
+    const ANNOUNCEMENT_ONLY_TITLES: &[&str] = &["请假", "感言", "上架"];
+    /// 正文这么短的时候才看其他公告的词
+    const ANNOUNCEMENT_MAX_CHARS: usize = 1000;
+
+    /// 按标题和正文判断是不是只有公告的章节
+    ///
+    /// 标题里有章节号的 ("第12章 请假的师兄") 只看正文长度不够, 还得正文里有公告的词.
+    /// 没有章节号的番外也可能叫 "新书发布会", 除了请假和感言, 正文长的都算正文
+    pub fn classify_chapter(title: &str, content: &ChapterContent) -> ChapterKind {
+        let numbering = ChapterNumbering::parse(title);
+        let has_keyword = |text: &str| ANNOUNCEMENT_TITLES.iter().any(|k| text.contains(k));
+        let short = content.char_count() <= ANNOUNCEMENT_MAX_CHARS;
+        let is_announcement = if numbering.number.is_none() {
+            ANNOUNCEMENT_ONLY_TITLES.iter().any(|k| title.contains(k))
+                || (short && (has_keyword(title) || has_keyword(&content.text())))
+        } else {
+            short && has_keyword(&numbering.name) && has_keyword(&content.text())
+        };
+        if is_announcement {
+            ChapterKind::Announcement
+        } else {
+            ChapterKind::Story
+        }
+    }
+
+    /// 导出时怎么处理 [`ExtraSection`]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum SectionPolicy {
+        /// 留在章节后面
+        #[default]
+        Keep,
+        /// 挪到书最后的附录里
+        Appendix,
+        /// 不要
+        Drop,
+    }
+
+    impl FromStr for SectionPolicy {
+        type Err = std::io::Error;
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+            match s.to_lowercase().as_str() {
+                "keep" => Ok(SectionPolicy::Keep),
+                "appendix" => Ok(SectionPolicy::Appendix),
+                "drop" => Ok(SectionPolicy::Drop),
+                _ => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Invalid section policy",
+                )),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_parse_content() {
+            let html = r#"
+                <div class="print"><h1 class="title">1.应杰</h1></div>
+                <div class="content">
+                    <p>　　第一段</p>
+                    <p><span>第二</span>段</p>
+                    <p>  </p>
+                </div>"#;
+            let content = parse(html);
+            assert_eq!(content.title.as_deref(), Some("1.应杰"));
+            assert_eq!(content.paragraphs, vec!["第一段", "第二段"]);
+            assert_eq!(content.text(), "第一段\n第二段");
+            assert_eq!(content.char_count(), 6);
+
+            let plain = parse("<h1>标题</h1>第一行<br>第二行\n第三行");
+            assert_eq!(plain.paragraphs, vec!["第一行", "第二行", "第三行"]);
+        }
+
+        #[test]
+        fn test_parse_sections() {
+            let html = r#"
+                <h1>12.收官</h1>
+                <p>正文第一段</p>
+                <p>PS正文里不是附言的开头也不算</p>
+                <p>正文第二段</p>
+                <p>（PS：今天还有一更）</p>
+                <p>谢谢大家</p>
+                <p>求月票！求推荐票！</p>
+                <div class="author-say"><p>作者有话说</p><p>感谢盟主</p></div>"#;
+            let content = parse(html);
+            assert_eq!(
+                content.paragraphs,
+                vec!["正文第一段", "PS正文里不是附言的开头也不算", "正文第二段"]
+            );
+            assert_eq!(content.char_count(), 5 + 15 + 5);
+            let kinds: Vec<_> = content
+                .sections
+                .iter()
+                .map(|s| (s.kind, s.paragraphs.len()))
+                .collect();
+            assert_eq!(
+                kinds,
+                [
+                    (SectionKind::AuthorNote, 2),
+                    (SectionKind::Appeal, 1),
+                    (SectionKind::AuthorNote, 2)
+                ]
+            );
+            assert_eq!(content.all_paragraphs().count(), 8);
+            assert!(content.full_text().ends_with("作者有话说\n感谢盟主"));
+        }
+
+        #[test]
+        fn test_mid_chapter_markers() {
+            let story = "少年握紧了手中的剑, 看着台下黑压压的人群, 心里却出奇地平静.";
+            let mut html = String::from("<h1>35.大比</h1><p>清晨, 钟声响彻山门.</p>");
+            html.push_str("<p>公告：宗门大比三日后开始, 内门弟子均可报名.</p>");
+            html.push_str("<p>作者君这个名字, 是他师父随口起的.</p>");
+            for _ in 0..10 {
+                html.push_str(&format!("<p>{story}</p>"));
+            }
+            html.push_str("<p>PS：今天还有一更</p>");
+            let content = parse(&html);
+            assert_eq!(content.paragraphs.len(), 13);
+            assert!(content.paragraphs[1].starts_with("公告："));
+            assert_eq!(content.sections.len(), 1);
+            assert_eq!(content.sections[0].kind, SectionKind::AuthorNote);
+            assert_eq!(content.sections[0].paragraphs, vec!["PS：今天还有一更"]);
+        }
+
+        #[test]
+        fn test_parse_images() {
+            let html = r#"
+                <h1>3.地图</h1>
+                <img src="//img.qidian.com/map.png" alt="地图">
+                <p>第一段</p>
+                <p><img src="/loading.gif" data-original="https://img.qidian.com/hero.jpg">第二段</p>
+                <p>第三段</p>
+                <img src="data:image/png;base64,AAAA">
+                <div class="author-say"><img src="https://img.qidian.com/author.png"></div>"#;
+            let content = parse(html);
+            assert_eq!(content.paragraphs, vec!["第一段", "第二段", "第三段"]);
+            assert_eq!(
+                content.images,
+                vec![
+                    ChapterImage {
+                        url: "https://img.qidian.com/map.png".to_string(),
+                        alt: "地图".to_string(),
+                        after: 0,
+                    },
+                    ChapterImage {
+                        url: "https://img.qidian.com/hero.jpg".to_string(),
+                        alt: String::new(),
+                        after: 2,
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_classify_chapter() {
+            let short = parse("<p>今天发烧了, 请假一天, 明天补上</p>");
+            let long = parse(&format!("<p>{}</p>", "正文".repeat(600)));
+            assert_eq!(
+                classify_chapter("请假条", &short),
+                ChapterKind::Announcement
+            );
+            assert_eq!(
+                classify_chapter("上架感言", &long),
+                ChapterKind::Announcement
+            );
+            assert_eq!(
+                classify_chapter("关于更新", &short),
+                ChapterKind::Announcement
+            );
+            assert_eq!(
+                classify_chapter("第12章 请假", &short),
+                ChapterKind::Announcement
+            );
+            assert_eq!(classify_chapter("第12章 请假", &long), ChapterKind::Story);
+            assert_eq!(classify_chapter("12.应杰", &short), ChapterKind::Story);
+
+            // 没有章节号的番外, 标题里有 "新书", "说明" 但是正文很长
+            assert_eq!(
+                classify_chapter("番外 新书发布会", &long),
+                ChapterKind::Story
+            );
+            assert_eq!(classify_chapter("使用说明", &long), ChapterKind::Story);
+            assert_eq!(
+                classify_chapter("新书发布", &short),
+                ChapterKind::Announcement
+            );
+        }
     }
 }
@@ -0,0 +1,275 @@
+//! 下载进度
+//!
+//! 终端里是一个实时进度条, 不是终端 (比如重定向到文件, 跑在 systemd 里) 时隔一段时间打印一行汇总
+//!
+//! 进度条画在 stderr 上, 日志和给人看的提示要经过 [`StderrWriter`] 和 [`println`], 不然会把进度条撕开
+
+use std::{
+    io::{IsTerminal, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::books::{BookChapter, BookInfo, BookVolume};
+
+/// 非终端环境下多久打印一次汇总
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 正在显示的进度条
+static ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+fn active_bar() -> Option<ProgressBar> {
+    ACTIVE_BAR.lock().unwrap().clone()
+}
+
+/// 打印一行给人看的信息, 有进度条的时候打在进度条上面
+pub fn println(msg: impl AsRef<str>) {
+    match active_bar() {
+        Some(bar) => bar.println(msg),
+        None => println!("{}", msg.as_ref()),
+    }
+}
+
+/// 给 tracing 用的 stderr, 有进度条时先把进度条藏起来再写
+pub struct StderrWriter;
+
+impl Write for StderrWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match active_bar() {
+            Some(bar) => bar.suspend(|| std::io::stderr().write(buf)),
+            None => std::io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+/// 纯计数部分, 和怎么显示无关
+#[derive(Debug, Clone)]
+pub struct ProgressStats {
+    pub total_chapters: usize,
+    pub total_words: u64,
+    /// 包括跳过的章节, 进度条按这个走
+    pub done_chapters: usize,
+    pub done_words: u64,
+    /// 上次已经下载过或者不用下载的章节, 不算进速度和剩余时间
+    pub skipped_chapters: usize,
+    pub skipped_words: u64,
+    pub volume: String,
+}
+
+impl ProgressStats {
+    pub fn new(book: &BookInfo) -> Self {
+        Self {
+            total_chapters: book.volumes.iter().map(|v| v.chapters.len()).sum(),
+            total_words: book.length() as u64,
+            done_chapters: 0,
+            done_words: 0,
+            skipped_chapters: 0,
+            skipped_words: 0,
+            volume: String::new(),
+        }
+    }
+
+    /// 这次真正下载了一章
+    pub fn chapter_done(&mut self, words: u64) {
+        self.done_chapters += 1;
+        self.done_words += words;
+    }
+
+    /// 跳过了一章, 进度往前走, 但是没花时间
+    pub fn chapter_skipped(&mut self, words: u64) {
+        self.chapter_done(words);
+        self.skipped_chapters += 1;
+        self.skipped_words += words;
+    }
+
+    /// 每分钟读多少章
+    pub fn chapters_per_minute(&self, elapsed: Duration) -> f64 {
+        if elapsed.is_zero() {
+            return 0.0;
+        }
+        (self.done_chapters - self.skipped_chapters) as f64 / elapsed.as_secs_f64() * 60.0
+    }
+
+    /// 按字数估计剩余时间
+    ///
+    /// 章节长短差别很大, 按字数算比按章数算准. 速度只看这次下载的字数
+    pub fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        let fetched = self.done_words - self.skipped_words;
+        if fetched == 0 {
+            return None;
+        }
+        let left = self.total_words.saturating_sub(self.done_words);
+        Some(elapsed.mul_f64(left as f64 / fetched as f64))
+    }
+
+    pub fn summary(&self, elapsed: Duration) -> String {
+        let eta = self
+            .eta(elapsed)
+            .map(format_duration)
+            .unwrap_or_else(|| "-".to_string());
+        format!(
+            "章节 {}/{} | 字数 {}/{} | {} | {:.1} 章/分 | 剩余 {eta}",
+            self.done_chapters,
+            self.total_chapters,
+            self.done_words,
+            self.total_words,
+            self.volume,
+            self.chapters_per_minute(elapsed),
+        )
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// 下载进度的显示
+pub struct DownloadProgress {
+    stats: ProgressStats,
+    start: Instant,
+    last_summary: Instant,
+    bar: Option<ProgressBar>,
+}
+
+impl DownloadProgress {
+    pub fn new(book: &BookInfo) -> Self {
+        let stats = ProgressStats::new(book);
+        let bar = std::io::stderr().is_terminal().then(|| {
+            let bar = ProgressBar::with_draw_target(
+                Some(stats.total_words),
+                ProgressDrawTarget::stderr(),
+            );
+            bar.set_style(
+                ProgressStyle::with_template("[{elapsed_precise}] {wide_bar} {percent:>3}%\n{msg}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar
+        });
+        *ACTIVE_BAR.lock().unwrap() = bar.clone();
+        let now = Instant::now();
+        Self {
+            stats,
+            start: now,
+            last_summary: now,
+            bar,
+        }
+    }
+
+    pub fn start_volume(&mut self, volume: &BookVolume) {
+        self.stats.volume = volume.title.clone();
+        self.refresh();
+    }
+
+    pub fn chapter_done(&mut self, chapter: &BookChapter) {
+        self.stats.chapter_done(chapter.length as u64);
+        self.refresh();
+    }
+
+    /// 不用下载的章节, 见 [`ProgressStats::chapter_skipped`]
+    pub fn chapter_skipped(&mut self, chapter: &BookChapter) {
+        self.stats.chapter_skipped(chapter.length as u64);
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        let elapsed = self.start.elapsed();
+        match &self.bar {
+            Some(bar) => {
+                bar.set_position(self.stats.done_words);
+                bar.set_message(self.stats.summary(elapsed));
+            }
+            None => {
+                if self.last_summary.elapsed() >= SUMMARY_INTERVAL {
+                    self.last_summary = Instant::now();
+                    println!("{}", self.stats.summary(elapsed));
+                }
+            }
+        }
+    }
+
+    pub fn finish(&self) {
+        let summary = self.stats.summary(self.start.elapsed());
+        match &self.bar {
+            Some(bar) => bar.finish_with_message(summary),
+            None => println!("{summary}"),
+        }
+    }
+}
+
+impl Drop for DownloadProgress {
+    fn drop(&mut self) {
+        *ACTIVE_BAR.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(done_chapters: usize, done_words: u64) -> ProgressStats {
+        ProgressStats {
+            total_chapters: 10,
+            total_words: 10_000,
+            done_chapters,
+            done_words,
+            skipped_chapters: 0,
+            skipped_words: 0,
+            volume: "正文卷".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_eta_by_words() {
+        assert_eq!(stats(0, 0).eta(Duration::from_secs(10)), None);
+        // 读了 1/4 的字数用了 60s, 剩下 3/4 要 180s, 和章数无关
+        assert_eq!(
+            stats(1, 2_500).eta(Duration::from_secs(60)),
+            Some(Duration::from_secs(180))
+        );
+        assert_eq!(
+            stats(9, 2_500).eta(Duration::from_secs(60)),
+            Some(Duration::from_secs(180))
+        );
+        assert_eq!(
+            stats(10, 10_000).eta(Duration::from_secs(60)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_resumed_run() {
+        // 上次已经下了 8 章, 这次只下了 1 章
+        let mut stats = stats(0, 0);
+        for _ in 0..8 {
+            stats.chapter_skipped(1_000);
+        }
+        assert_eq!(stats.eta(Duration::from_secs(60)), None);
+        assert_eq!(stats.chapters_per_minute(Duration::from_secs(60)), 0.0);
+        stats.chapter_done(1_000);
+        assert_eq!((stats.done_chapters, stats.done_words), (9, 9_000));
+        // 1000 字用了 60s, 还剩 1000 字
+        assert_eq!(
+            stats.eta(Duration::from_secs(60)),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(stats.chapters_per_minute(Duration::from_secs(60)), 1.0);
+    }
+
+    #[test]
+    fn test_rate_and_format() {
+        assert_eq!(
+            stats(5, 0).chapters_per_minute(Duration::from_secs(30)),
+            10.0
+        );
+        assert_eq!(stats(5, 0).chapters_per_minute(Duration::ZERO), 0.0);
+        assert_eq!(format_duration(Duration::from_secs(3723)), "01:02:03");
+    }
+}
@@ -0,0 +1,267 @@
+//! 访问频率控制
+//!
+//! `Driver` 的每次页面跳转都要先过这里, 全部用 tokio 的计时器, 不会卡住 worker
+
+use std::{collections::VecDeque, str::FromStr, time::Duration};
+
+use rand::Rng;
+use tokio::{sync::Mutex, time::Instant};
+use tracing::info;
+
+use crate::CliArg;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// 随机抖动的分布
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    /// 不抖动
+    None,
+    /// `[0, max)` 均匀分布
+    Uniform(Duration),
+    /// 均值为 `mean` 的指数分布, 偶尔会停得比较久, 更像人
+    Exponential(Duration),
+}
+
+impl FromStr for Jitter {
+    type Err = std::io::Error;
+    /// `none`, `uniform:<毫秒>`, `exp:<毫秒>`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid jitter, expect none, uniform:<ms> or exp:<ms>",
+            )
+        };
+        let s = s.trim().to_lowercase();
+        if s == "none" {
+            return Ok(Jitter::None);
+        }
+        let (kind, ms) = s.split_once(':').ok_or_else(invalid)?;
+        let ms = Duration::from_millis(ms.trim().parse().map_err(|_| invalid())?);
+        match kind.trim() {
+            "uniform" => Ok(Jitter::Uniform(ms)),
+            "exp" | "exponential" => Ok(Jitter::Exponential(ms)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Jitter {
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match *self {
+            Jitter::None => Duration::ZERO,
+            Jitter::Uniform(max) if max.is_zero() => Duration::ZERO,
+            Jitter::Uniform(max) => max.mul_f64(rng.random::<f64>()),
+            Jitter::Exponential(mean) => {
+                let u: f64 = rng.random();
+                // 截断一下, 不然极小概率会停个几十分钟
+                mean.mul_f64((-(1.0 - u).ln()).min(10.0))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// 两次跳转之间至少间隔多久
+    pub min_delay: Duration,
+    /// 在 `min_delay` 之上再加的随机时间
+    pub jitter: Jitter,
+    /// 每小时最多存多少章
+    pub chapters_per_hour: Option<u32>,
+    /// 每存这么多章多歇一会
+    pub pause_every: Option<u32>,
+    /// 多歇多久
+    pub pause: Duration,
+}
+
+impl RateLimitConfig {
+    pub fn from_cli(config: &CliArg) -> Self {
+        Self {
+            min_delay: Duration::from_millis(config.min_delay_ms),
+            jitter: config.jitter,
+            chapters_per_hour: config.chapters_per_hour.filter(|n| *n > 0),
+            pause_every: config.pause_every.filter(|n| *n > 0),
+            pause: Duration::from_secs(config.pause_secs),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    last: Option<Instant>,
+    /// 最近一小时内存下章节的时间
+    recent_chapters: VecDeque<Instant>,
+    chapters: u64,
+    /// 上次定期休息时存了多少章, 同一个数不重复休息
+    paused_at: u64,
+}
+
+/// 访问频率控制器, 内部加锁, 所有跳转共用一个
+#[derive(Debug)]
+pub struct RateLimiter {
+    cfg: RateLimitConfig,
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new(cfg: RateLimitConfig) -> Self {
+        Self {
+            cfg,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    /// 在跳转之前调用, 等到可以跳转为止
+    ///
+    /// `is_chapter` 为 true 时这次跳转要打开一章, 先看每小时上限和定期休息.
+    /// 计数的是 [`Self::chapter_saved`], 打开失败的章节不算
+    pub async fn acquire(&self, is_chapter: bool) {
+        let mut state = self.state.lock().await;
+
+        if is_chapter {
+            if let Some(cap) = self.cfg.chapters_per_hour {
+                let now = Instant::now();
+                while state
+                    .recent_chapters
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= HOUR)
+                {
+                    state.recent_chapters.pop_front();
+                }
+                if state.recent_chapters.len() >= cap as usize {
+                    let oldest = *state.recent_chapters.front().unwrap();
+                    info!(cap, "已达到每小时章节上限, 等一会再继续");
+                    tokio::time::sleep_until(oldest + HOUR).await;
+                    state.recent_chapters.pop_front();
+                }
+            }
+            if let Some(every) = self.cfg.pause_every
+                && state.chapters > state.paused_at
+                && state.chapters.is_multiple_of(every as u64)
+            {
+                state.paused_at = state.chapters;
+                info!(
+                    chapters = state.chapters,
+                    pause_secs = self.cfg.pause.as_secs(),
+                    "定期休息"
+                );
+                tokio::time::sleep(self.cfg.pause).await;
+            }
+        }
+
+        let jitter = self.cfg.jitter.sample(&mut rand::rng());
+        if let Some(last) = state.last {
+            tokio::time::sleep_until(last + self.cfg.min_delay + jitter).await;
+        }
+
+        state.last = Some(Instant::now());
+    }
+
+    /// 存下了一章, 计入每小时上限和定期休息
+    pub async fn chapter_saved(&self) {
+        let mut state = self.state.lock().await;
+        state.chapters += 1;
+        state.recent_chapters.push_back(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            min_delay: Duration::from_secs(2),
+            jitter: Jitter::None,
+            chapters_per_hour: None,
+            pause_every: None,
+            pause: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_parse_jitter() {
+        assert_eq!("none".parse::<Jitter>().unwrap(), Jitter::None);
+        assert_eq!(
+            "uniform:500".parse::<Jitter>().unwrap(),
+            Jitter::Uniform(Duration::from_millis(500))
+        );
+        assert_eq!(
+            "exp:800".parse::<Jitter>().unwrap(),
+            Jitter::Exponential(Duration::from_millis(800))
+        );
+        assert!("gauss:1".parse::<Jitter>().is_err());
+        assert!("uniform".parse::<Jitter>().is_err());
+    }
+
+    #[test]
+    fn test_jitter_range() {
+        let mut rng = rand::rng();
+        let max = Duration::from_millis(100);
+        for _ in 0..1000 {
+            assert!(Jitter::Uniform(max).sample(&mut rng) < max);
+            assert!(Jitter::Exponential(max).sample(&mut rng) <= max * 10);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_min_delay() {
+        let limiter = RateLimiter::new(config());
+        let start = Instant::now();
+        // 第一次不用等
+        limiter.acquire(true).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.acquire(false).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        limiter.acquire(true).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause_every() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            pause_every: Some(3),
+            pause: Duration::from_secs(60),
+            ..config()
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire(true).await;
+            limiter.chapter_saved().await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+        // 第四章之前歇一分钟, 歇完已经超过 min_delay 了
+        limiter.acquire(true).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(64));
+        // 第四章没存下来, 重新打开不会再歇
+        limiter.acquire(true).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(66));
+        // 非章节跳转不算数
+        limiter.acquire(false).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(68));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_chapters_per_hour() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            chapters_per_hour: Some(2),
+            ..config()
+        });
+        let start = Instant::now();
+        limiter.acquire(true).await;
+        limiter.chapter_saved().await;
+        // 打开失败的章节不占名额
+        limiter.acquire(true).await;
+        limiter.acquire(true).await;
+        limiter.chapter_saved().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+        // 第三章要等到第一章满一小时
+        limiter.acquire(true).await;
+        limiter.chapter_saved().await;
+        assert_eq!(start.elapsed(), HOUR);
+        limiter.acquire(true).await;
+        assert_eq!(start.elapsed(), HOUR + Duration::from_secs(4));
+    }
+}
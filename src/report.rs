@@ -0,0 +1,76 @@
+//! 一次运行的报告
+//!
+//! 下载过程中遇到的验证, 登录失效, 出错之类的事情都记在这里, 结束时写到输出目录
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::ids::{BookId, ChapterId};
+
+/// 事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunEventKind {
+    /// 遇到人机验证
+    Challenge,
+    /// 被要求重新登录
+    LoginWall,
+    /// 没订阅的章节
+    Unpurchased,
+    /// 页面出错
+    PageError,
+    /// 插图下载失败, 章节照常保存
+    MissingImage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunEvent {
+    /// unix 时间戳 (秒)
+    pub time: i64,
+    pub kind: RunEventKind,
+    pub chapter_id: Option<ChapterId>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    pub book_id: BookId,
+    pub started: i64,
+    pub events: Vec<RunEvent>,
+}
+
+impl RunReport {
+    pub fn new(book_id: impl Into<BookId>) -> Self {
+        Self {
+            book_id: book_id.into(),
+            started: crate::cookies::now(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        kind: RunEventKind,
+        chapter_id: Option<&ChapterId>,
+        message: impl Into<String>,
+    ) {
+        self.events.push(RunEvent {
+            time: crate::cookies::now(),
+            kind,
+            chapter_id: chapter_id.cloned(),
+            message: message.into(),
+        });
+    }
+
+    pub fn count(&self, kind: RunEventKind) -> usize {
+        self.events
+            .iter()
+            .filter(|event| event.kind == kind)
+            .count()
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        crate::fs_util::write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+}
@@ -0,0 +1,295 @@
+//! 不开浏览器, 重新处理以前下载好的输出目录
+//!
+//! 卷和章的顺序从目录名和文件名前面的序号来, 也可以给一份保存下来的目录 html
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use tracing::{info, warn};
+
+use crate::{
+    books::BookInfo,
+    export::{self, ExportFormat, ExportOptions},
+    fs_util::{DirLock, read_input, write_atomic},
+    ids::{BookId, ChapterId},
+    images::ImageIndex,
+    library::{self, CATALOG_FILE},
+    manifest::Manifest,
+    parse_page::{book_info, chapter_content, reader_page},
+    revisions,
+    storage::{self, ChapterSink},
+};
+
+/// 检查的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckSummary {
+    pub stored: usize,
+    /// 目录里有, 存储里没有
+    pub missing: Vec<ChapterId>,
+    /// 存下来了但是解析不出正文
+    pub empty: Vec<ChapterId>,
+    /// 没订阅: 清单里记着的, 或者存下来的只是试读部分
+    pub locked: Vec<ChapterId>,
+    /// 存储里有, 目录里没有
+    pub unlisted: usize,
+}
+
+/// 按目录给存储里的章节重新生成清单, 顺便做个简单的检查
+///
+/// 目录里标着没订阅又没存正文的章节, 清单里还是记成没订阅.
+/// 旧清单里的历史版本记录从 `previous` 里搬过来, 这些从正文里推不出来
+pub fn rebuild_manifest(
+    book: &BookInfo,
+    sink: &dyn ChapterSink,
+    previous: &Manifest,
+) -> anyhow::Result<(Manifest, CheckSummary)> {
+    let keys: HashMap<_, _> = sink
+        .list()?
+        .into_iter()
+        .map(|key| (key.id.clone(), key))
+        .collect();
+    let mut manifest = Manifest::new(&book.id);
+    let mut summary = CheckSummary::default();
+    for volume in &book.volumes {
+        for chapter in &volume.chapters {
+            let (Some(key), Some(html)) = (keys.get(&chapter.id), sink.get(&chapter.id)?) else {
+                if chapter.is_locked() {
+                    manifest.record_locked(volume, chapter);
+                    summary.locked.push(chapter.id.clone());
+                } else {
+                    summary.missing.push(chapter.id.clone());
+                }
+                continue;
+            };
+            if reader_page::is_truncated(&html, chapter.length) {
+                // 老版本把试读页当正文存了
+                manifest.record_locked(volume, chapter);
+                summary.locked.push(chapter.id.clone());
+                continue;
+            }
+            if chapter_content::parse(&html).paragraphs.is_empty() {
+                summary.empty.push(chapter.id.clone());
+            }
+            manifest.record(
+                volume,
+                chapter,
+                key.relative_path(),
+                revisions::content_hash(&html),
+            );
+            summary.stored += 1;
+        }
+    }
+    // 试读页之类没记成已保存的章节也在目录里, 不算
+    let listed: HashSet<_> = book
+        .volumes
+        .iter()
+        .flat_map(|volume| &volume.chapters)
+        .map(|chapter| &chapter.id)
+        .collect();
+    summary.unlisted = keys.keys().filter(|id| !listed.contains(id)).count();
+    for (id, entry) in &mut manifest.chapters {
+        if let Some(old) = previous.chapters.get(id) {
+            entry.revisions = old.revisions.clone();
+        }
+    }
+    Ok((manifest, summary))
+}
+
+/// `reprocess` 子命令
+pub fn run(
+    config: &crate::CliArg,
+    dir: &str,
+    catalog: Option<&str>,
+    book_id: Option<&BookId>,
+    formats: &[ExportFormat],
+    options: &ExportOptions,
+) -> anyhow::Result<()> {
+    let dir = Path::new(dir);
+    // 会重写清单, 目录和导出文件, 不能和下载同时进行
+    let _lock = DirLock::acquire(dir)?;
+    let sink = storage::open_existing(dir)?;
+    let mut book = match catalog {
+        Some(path) => {
+            let (mut book, warnings) = book_info::parse_document(&read_input(path)?);
+            for warning in &warnings {
+                warn!("目录有问题: {warning}");
+            }
+            let dir_name = dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if book.id.is_empty() {
+                book.id = BookId::new(dir_name.clone());
+            }
+            if book.title.is_empty() {
+                book.title = dir_name;
+            }
+            book
+        }
+        None => library::load_catalog(dir, sink.as_ref())?,
+    };
+    if let Some(book_id) = book_id {
+        book.id = book_id.clone();
+    }
+    info!(book_id = %book.id, volumes = book.volumes.len(), "目录已就绪");
+
+    let manifest_path = dir.join(Manifest::FILE_NAME);
+    let previous = Manifest::load_for(&manifest_path, &book.id)?;
+    previous.apply_locked(&mut book);
+    let (manifest, summary) = rebuild_manifest(&book, sink.as_ref(), &previous)?;
+    manifest.save(&manifest_path)?;
+    manifest.apply_locked(&mut book);
+    if !dir.join(CATALOG_FILE).exists() {
+        write_atomic(
+            &dir.join(CATALOG_FILE),
+            serde_json::to_string_pretty(&book)?,
+        )?;
+    }
+    println!(
+        "《{}》: 已保存 {} 章, 缺 {} 章, {} 章没有正文",
+        book.title,
+        summary.stored,
+        summary.missing.len(),
+        summary.empty.len()
+    );
+    if summary.unlisted > 0 {
+        warn!(
+            unlisted = summary.unlisted,
+            "存储里有目录里没有的章节, 没有导出"
+        );
+    }
+    for id in &summary.empty {
+        warn!(chapter_id = %id, "章节没有正文");
+    }
+    if !summary.locked.is_empty() {
+        println!("{} 章没订阅:", summary.locked.len());
+        for (id, entry) in manifest.locked() {
+            println!("  {} ({id})", entry.title);
+        }
+    }
+
+    if config.since.is_some() || config.until.is_some() {
+        book.retain_released(config.since, config.until);
+    }
+    let options = ExportOptions {
+        images: ImageIndex::load(dir)?,
+        ..options.clone()
+    };
+    for format in formats {
+        let (path, stats) = export::export_book(&book, sink.as_ref(), *format, &options, dir)?;
+        println!("导出 {path:?}: {} 章", stats.chapters);
+        if !stats.skipped.is_empty() {
+            println!("  跳过了 {} 个请假条之类的章节", stats.skipped.len());
+        }
+        if !stats.missing_images.is_empty() && *format != ExportFormat::Txt {
+            println!(
+                "  {} 张插图没有下载, 用的是原来的地址",
+                stats.missing_images.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::DirSink;
+
+    #[test]
+    fn test_reprocess_old_layout() {
+        let dir = std::env::temp_dir().join(format!("qidian-reprocess-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("0_正文卷")).unwrap();
+        std::fs::write(
+            dir.join("0_正文卷/0_1.应杰-748679604.html"),
+            "<h1>1.应杰</h1><p>第一段</p>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("0_正文卷/1_2.时代的眼泪-748712345.html"), "").unwrap();
+        // 老版本把试读页当正文存了
+        std::fs::write(
+            dir.join("0_正文卷/2_3.试读-748798765.html"),
+            "<h1>3.试读</h1><p>开头</p><div class=\"vip-limit-wrap\">订阅本章</div>",
+        )
+        .unwrap();
+        // 老版本最后平铺复制的文件, 没有序号
+        std::fs::write(dir.join("1.应杰-748679604"), "<p>第一段</p>").unwrap();
+
+        let sink = DirSink::open(&dir).unwrap();
+        let book = library::load_catalog(&dir, &sink).unwrap();
+        assert_eq!(book.volumes.len(), 1);
+        assert_eq!(book.volumes[0].chapters[1].title, "2.时代的眼泪");
+
+        let (manifest, summary) = rebuild_manifest(&book, &sink, &Manifest::default()).unwrap();
+        assert_eq!(summary.stored, 2);
+        assert_eq!(summary.empty, vec!["748712345"]);
+        assert_eq!(summary.locked, vec!["748798765"]);
+        // 试读页还在存储里, 但它在目录里
+        assert_eq!(summary.unlisted, 0);
+        assert!(manifest.is_locked(&ChapterId::new("748798765")));
+        assert!(!manifest.contains(&ChapterId::new("748798765")));
+        assert!(summary.missing.is_empty());
+        assert_eq!(
+            manifest.chapters["748679604"].path,
+            "0_正文卷/0_1.应杰-748679604.html"
+        );
+
+        // 目录里同一章出现两次
+        let mut duplicated = book.clone();
+        let first = duplicated.volumes[0].chapters[0].clone();
+        duplicated.volumes[0].chapters.push(first);
+        let (_, summary) = rebuild_manifest(&duplicated, &sink, &Manifest::default()).unwrap();
+        assert_eq!(summary.stored, 3);
+        assert_eq!(summary.unlisted, 0);
+
+        let config = <crate::CliArg as clap::Parser>::parse_from(["qidian-downloader"]);
+        let reprocess = || {
+            run(
+                &config,
+                dir.to_str().unwrap(),
+                None,
+                Some(&BookId::new("1036741406")),
+                &[ExportFormat::Txt],
+                &ExportOptions::default(),
+            )
+        };
+        // 以前下载时记下的历史版本
+        let mut previous = manifest.clone();
+        previous.book_id = BookId::new("1036741406");
+        let entry = previous.chapters.get_mut("748679604").unwrap();
+        entry.revisions.push(crate::manifest::Revision {
+            number: 1,
+            hash: "old".to_string(),
+            length: 10,
+            replaced_at: 1_700_000_000,
+        });
+        let revision = revisions::revision_path(&dir, &ChapterId::new("748679604"), 1);
+        std::fs::create_dir_all(revision.parent().unwrap()).unwrap();
+        std::fs::write(&revision, "<p>旧的第一段</p>").unwrap();
+        previous.save(&dir.join(Manifest::FILE_NAME)).unwrap();
+
+        // 正在下载的目录不能重新处理
+        let lock = DirLock::acquire(&dir).unwrap();
+        assert!(reprocess().is_err());
+        drop(lock);
+        reprocess().unwrap();
+        let manifest =
+            Manifest::load_for(&dir.join(Manifest::FILE_NAME), &BookId::new("1036741406")).unwrap();
+        assert_eq!(manifest.chapters["748679604"].revisions.len(), 1);
+        assert!(manifest.chapters["748712345"].revisions.is_empty());
+        let catalog = std::fs::read_to_string(dir.join(CATALOG_FILE)).unwrap();
+        assert!(catalog.contains("1036741406"));
+        let txt = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .map(|path| std::fs::read_to_string(path).unwrap())
+            .unwrap();
+        assert!(txt.contains("3.试读\n\n　　（本章未订阅, 没有正文）"));
+        assert!(!txt.contains("开头"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+//! 章节的历史版本
+//!
+//! 作者发布后还会改章节, 重新下载时如果正文变了, 旧的 html 存到
+//! `<书目录>/revisions/<章节 id>/<编号>.html`, 编号记在清单里
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    books::BookChapter,
+    ids::{BookId, ChapterId},
+    manifest::{Manifest, Revision},
+    parse_page::chapter_content,
+    storage::{self, ChapterSink},
+};
+
+/// 正文的 sha256
+///
+/// 只算解析出来的段落 (包括作者的话), 页面上别的东西 (广告, 评论数) 变了不算改过
+pub fn content_hash(html: &str) -> String {
+    let text = chapter_content::parse(html).full_text();
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+pub fn revision_path(book_dir: &Path, chapter_id: &ChapterId, number: u32) -> PathBuf {
+    book_dir
+        .join("revisions")
+        .join(storage::sanitize(chapter_id.as_str()))
+        .join(format!("{number}.html"))
+}
+
+/// 新下载的正文和存储里的不一样时, 把存储里的旧版本存成一个历史版本
+///
+/// 返回新的历史版本编号, 没变或者以前没存过返回 `None`
+pub fn archive_if_changed(
+    book_dir: &Path,
+    manifest: &mut Manifest,
+    sink: &dyn ChapterSink,
+    chapter: &BookChapter,
+    new_hash: &str,
+) -> anyhow::Result<Option<u32>> {
+    let Some(entry) = manifest.chapters.get_mut(&chapter.id) else {
+        return Ok(None);
+    };
+    let Some(old_html) = sink.get(&chapter.id)? else {
+        return Ok(None);
+    };
+    let old_hash = if entry.hash.is_empty() {
+        content_hash(&old_html)
+    } else {
+        entry.hash.clone()
+    };
+    if old_hash == new_hash {
+        return Ok(None);
+    }
+
+    let number = entry.revisions.last().map_or(1, |r| r.number + 1);
+    let path = revision_path(book_dir, &chapter.id, number);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    crate::fs_util::write_atomic(&path, &old_html)?;
+    entry.revisions.push(Revision {
+        number,
+        hash: old_hash,
+        length: entry.length,
+        replaced_at: crate::cookies::now(),
+    });
+    Ok(Some(number))
+}
+
+/// 段落级别的差异
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// 按段落比较两个版本 (最长公共子序列)
+pub fn diff_paragraphs<'a>(old: &'a [String], new: &'a [String]) -> Vec<DiffLine<'a>> {
+    // lcs[i][j]: old[i..] 和 new[j..] 的最长公共子序列长度
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Same(&old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(DiffLine::Removed(&old[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(&new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|p| DiffLine::Removed(p)));
+    lines.extend(new[j..].iter().map(|p| DiffLine::Added(p)));
+    lines
+}
+
+/// `revisions` 子命令: 列出一章的历史版本, 显示两个版本之间的差异
+///
+/// 版本号 0 表示当前版本; 不指定时比较最后一个历史版本和当前版本
+pub fn run(
+    config: &crate::CliArg,
+    book_id: &BookId,
+    chapter_id: &ChapterId,
+    from: Option<u32>,
+    to: Option<u32>,
+) -> anyhow::Result<()> {
+    let book_dir = Path::new(&config.out_dir).join(book_id.as_str());
+    let manifest = Manifest::load_for(&book_dir.join(Manifest::FILE_NAME), book_id)?;
+    let entry = manifest
+        .chapters
+        .get(chapter_id)
+        .with_context(|| format!("清单里没有章节 {chapter_id}"))?;
+
+    println!("{} ({chapter_id})", entry.title);
+    for revision in &entry.revisions {
+        println!(
+            "  #{} 字数 {} 替换于 {} ({})",
+            revision.number,
+            revision.length,
+            revision.replaced_at,
+            &revision.hash[..revision.hash.len().min(12)]
+        );
+    }
+    println!(
+        "  #0 (当前) 字数 {} ({})",
+        entry.length,
+        &entry.hash[..entry.hash.len().min(12)]
+    );
+    let Some(last) = entry.revisions.last() else {
+        println!("没有历史版本");
+        return Ok(());
+    };
+
+    let sink = storage::open_existing(&book_dir)?;
+    let load = |number: u32| -> anyhow::Result<Vec<String>> {
+        let html = if number == 0 {
+            sink.get(chapter_id)?
+                .with_context(|| format!("存储里没有章节 {chapter_id}"))?
+        } else {
+            let path = revision_path(&book_dir, chapter_id, number);
+            std::fs::read_to_string(&path).with_context(|| format!("读取 {path:?} 失败"))?
+        };
+        Ok(chapter_content::parse(&html)
+            .all_paragraphs()
+            .cloned()
+            .collect())
+    };
+    let from = from.unwrap_or(last.number);
+    let to = to.unwrap_or(0);
+    let (old, new) = (load(from)?, load(to)?);
+
+    println!("--- #{from}\n+++ #{to}");
+    for line in diff_paragraphs(&old, &new) {
+        match line {
+            DiffLine::Same(_) => {}
+            DiffLine::Removed(p) => println!("- {p}"),
+            DiffLine::Added(p) => println!("+ {p}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn paragraphs(s: &str) -> Vec<String> {
+        s.split('|').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_diff_paragraphs() {
+        let old = paragraphs("一|二|三|四");
+        let new = paragraphs("一|二改|三|四|五");
+        assert_eq!(
+            diff_paragraphs(&old, &new),
+            vec![
+                DiffLine::Same("一"),
+                DiffLine::Removed("二"),
+                DiffLine::Added("二改"),
+                DiffLine::Same("三"),
+                DiffLine::Same("四"),
+                DiffLine::Added("五"),
+            ]
+        );
+        assert!(
+            diff_paragraphs(&old, &old)
+                .iter()
+                .all(|line| matches!(line, DiffLine::Same(_)))
+        );
+    }
+
+    #[test]
+    fn test_content_hash_ignores_markup() {
+        let a = r#"<h1>1.应杰</h1><p>第一段</p><p>第二段</p>"#;
+        let b = r#"<h1>1.应杰</h1><div class="ad">广告</div><p> 第一段 </p><p>第二段</p>"#;
+        let c = r#"<h1>1.应杰</h1><p>第一段</p><p>第二段改</p>"#;
+        assert_eq!(content_hash(a), content_hash(b));
+        assert_ne!(content_hash(a), content_hash(c));
+    }
+}
@@ -0,0 +1,111 @@
+//! Ctrl+C 处理
+//!
+//! 第一次 Ctrl+C 只是发出取消信号, 下载循环在安全的地方停下来, 保存进度并关掉 webdriver 会话;
+//! 第二次 Ctrl+C 直接退出
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tracing::warn;
+
+/// 被 Ctrl+C 正常取消后的退出码
+pub const EXIT_INTERRUPTED: i32 = 130;
+/// 连按两次 Ctrl+C 强制退出的退出码
+pub const EXIT_FORCED: i32 = 131;
+
+/// 下载被用户取消
+#[derive(Debug, Clone, Copy)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "下载被用户取消")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// 取消信号, 可以随便 clone
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl Shutdown {
+    /// 在后台监听 Ctrl+C
+    pub fn listen(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!("收到 Ctrl+C, 当前章节处理完就停下 (再按一次强制退出)");
+            shutdown.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                eprintln!("强制退出");
+                std::process::exit(EXIT_FORCED);
+            }
+        });
+    }
+
+    pub fn cancel(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// 等到被取消为止
+    pub async fn cancelled(&self) {
+        let mut rx = self.tx.subscribe();
+        // sender 在 self 里, 不会被 drop, 这里不会出错
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// 运行 `fut`, 中途被取消就返回 [`Interrupted`]
+    pub async fn run<T>(&self, fut: impl Future<Output = anyhow::Result<T>>) -> anyhow::Result<T> {
+        tokio::select! {
+            result = fut => result,
+            _ = self.cancelled() => Err(Interrupted.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.is_cancelled());
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.cancelled().await }
+        });
+        let slow = shutdown.run(async {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Ok(())
+        });
+
+        shutdown.cancel();
+        let err = slow.await.unwrap_err();
+        assert!(err.is::<Interrupted>());
+        waiter.await.unwrap();
+        assert!(shutdown.is_cancelled());
+        // 已经取消之后再等也会立刻返回
+        shutdown.cancelled().await;
+    }
+}
@@ -0,0 +1,649 @@
+//! 章节存到哪里
+//!
+//! 下载, 断点续传, 导出都只通过 [`ChapterSink`] 读写章节, 换存储方式只需要改 `--storage`
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use tracing::warn;
+
+use crate::{
+    books::{BookChapter, BookVolume},
+    fs_util::{sync_parent, write_atomic},
+    ids::ChapterId,
+};
+
+/// 存储方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageKind {
+    /// 每章一个 html 文件, 按卷分目录
+    #[default]
+    Dir,
+    /// 整本书一个 zip
+    Zip,
+    /// 整本书一个 sqlite 数据库
+    Sqlite,
+}
+
+impl FromStr for StorageKind {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dir" => Ok(StorageKind::Dir),
+            "zip" => Ok(StorageKind::Zip),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid storage type",
+            )),
+        }
+    }
+}
+
+/// 打开 `root` 目录下的存储
+pub fn open_sink(kind: StorageKind, root: &Path) -> anyhow::Result<Box<dyn ChapterSink>> {
+    std::fs::create_dir_all(root)?;
+    Ok(match kind {
+        StorageKind::Dir => Box::new(DirSink::open(root)?),
+        StorageKind::Zip => Box::new(ZipSink::open(root.join(ZipSink::FILE_NAME))?),
+        StorageKind::Sqlite => Box::new(SqliteSink::open(&root.join(SqliteSink::FILE_NAME))?),
+    })
+}
+
+/// 打开 `root` 目录下已有的存储, 按里面的文件判断是哪种
+pub fn open_existing(root: &Path) -> anyhow::Result<Box<dyn ChapterSink>> {
+    anyhow::ensure!(root.is_dir(), "{root:?} 不是目录");
+    let kind = if root.join(SqliteSink::FILE_NAME).exists() {
+        StorageKind::Sqlite
+    } else if root.join(ZipSink::FILE_NAME).exists() {
+        StorageKind::Zip
+    } else {
+        StorageKind::Dir
+    };
+    open_sink(kind, root)
+}
+
+/// 一章在存储里的位置
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChapterKey {
+    /// 卷在书里的序号, 从 0 开始
+    pub volume_index: usize,
+    pub volume_title: String,
+    /// 章在卷里的序号, 从 0 开始
+    pub chapter_index: usize,
+    pub title: String,
+    pub id: ChapterId,
+}
+
+impl ChapterKey {
+    pub fn new(
+        volume_index: usize,
+        volume: &BookVolume,
+        chapter_index: usize,
+        chapter: &BookChapter,
+    ) -> Self {
+        Self {
+            volume_index,
+            volume_title: volume.title.clone(),
+            chapter_index,
+            title: chapter.title.clone(),
+            id: chapter.id.clone(),
+        }
+    }
+
+    /// `<卷序号>_<卷名>`
+    pub fn volume_dir(&self) -> String {
+        format!("{}_{}", self.volume_index, sanitize(&self.volume_title))
+    }
+
+    /// `<章序号>_<章名>-<章节 id>.html`
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}_{}-{}.html",
+            self.chapter_index,
+            sanitize(&self.title),
+            self.id
+        )
+    }
+
+    /// `<卷序号>_<卷名>/<章序号>_<章名>-<章节 id>.html`
+    pub fn relative_path(&self) -> String {
+        format!("{}/{}", self.volume_dir(), self.file_name())
+    }
+
+    /// 从目录名和文件名反推出 key, 名字不对就是 `None`
+    pub fn parse_path(volume_dir: &str, file_name: &str) -> Option<Self> {
+        let (volume_index, volume_title) = volume_dir.split_once('_')?;
+        let stem = file_name.strip_suffix(".html")?;
+        let (chapter_index, rest) = stem.split_once('_')?;
+        let (title, id) = rest.rsplit_once('-')?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(Self {
+            volume_index: volume_index.parse().ok()?,
+            volume_title: volume_title.to_string(),
+            chapter_index: chapter_index.parse().ok()?,
+            title: title.to_string(),
+            id: ChapterId::new(id),
+        })
+    }
+
+    fn sort_key(&self) -> (usize, usize) {
+        (self.volume_index, self.chapter_index)
+    }
+}
+
+/// 把文件名里不能用的字符换掉
+pub fn sanitize(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// 章节的存储
+pub trait ChapterSink: Send {
+    /// 保存一章, 已经有了就覆盖
+    fn put(&mut self, key: &ChapterKey, html: &str) -> anyhow::Result<()>;
+    /// 这一章是否已经存过
+    fn exists(&self, chapter_id: &ChapterId) -> anyhow::Result<bool>;
+    /// 读一章的内容
+    fn get(&self, chapter_id: &ChapterId) -> anyhow::Result<Option<String>>;
+    /// 已经存了的所有章节, 按卷和章的顺序
+    fn list(&self) -> anyhow::Result<Vec<ChapterKey>>;
+}
+
+/// 按卷分目录, 每章一个 html 文件, 也就是最早的 `out/` 布局
+#[derive(Debug)]
+pub struct DirSink {
+    root: PathBuf,
+    /// 章节 id -> key
+    index: HashMap<ChapterId, ChapterKey>,
+}
+
+impl DirSink {
+    pub fn open(root: &Path) -> anyhow::Result<Self> {
+        let mut index = HashMap::new();
+        for key in scan_dir(root)? {
+            index.insert(key.id.clone(), key);
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            index,
+        })
+    }
+}
+
+/// 扫描按卷分目录的章节文件, 名字不符合格式的文件会被忽略
+pub fn scan_dir(root: &Path) -> anyhow::Result<Vec<ChapterKey>> {
+    let mut keys = Vec::new();
+    if !root.is_dir() {
+        return Ok(keys);
+    }
+    for volume in std::fs::read_dir(root)? {
+        let volume = volume?;
+        if !volume.file_type()?.is_dir() {
+            continue;
+        }
+        let volume_dir = volume.file_name().to_string_lossy().to_string();
+        for chapter in std::fs::read_dir(volume.path())? {
+            let file_name = chapter?.file_name().to_string_lossy().to_string();
+            if let Some(key) = ChapterKey::parse_path(&volume_dir, &file_name) {
+                keys.push(key);
+            }
+        }
+    }
+    keys.sort_by_key(ChapterKey::sort_key);
+    Ok(keys)
+}
+
+impl ChapterSink for DirSink {
+    fn put(&mut self, key: &ChapterKey, html: &str) -> anyhow::Result<()> {
+        let dir = self.root.join(key.volume_dir());
+        std::fs::create_dir_all(&dir)?;
+        write_atomic(&dir.join(key.file_name()), html)?;
+        if let Some(old) = self.index.insert(key.id.clone(), key.clone())
+            && old.relative_path() != key.relative_path()
+        {
+            // 章名或者位置变了, 旧文件删掉免得重复
+            let _ = std::fs::remove_file(self.root.join(old.relative_path()));
+        }
+        Ok(())
+    }
+
+    fn exists(&self, chapter_id: &ChapterId) -> anyhow::Result<bool> {
+        Ok(self.index.contains_key(chapter_id))
+    }
+
+    fn get(&self, chapter_id: &ChapterId) -> anyhow::Result<Option<String>> {
+        match self.index.get(chapter_id) {
+            Some(key) => Ok(Some(std::fs::read_to_string(
+                self.root.join(key.relative_path()),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self) -> anyhow::Result<Vec<ChapterKey>> {
+        let mut keys: Vec<_> = self.index.values().cloned().collect();
+        keys.sort_by_key(ChapterKey::sort_key);
+        Ok(keys)
+    }
+}
+
+/// 整本书存成一个 zip, 里面的路径和 [`DirSink`] 一样
+///
+/// 新章节直接追加到 zip 末尾. 追加会覆盖 zip 末尾的中央目录, 所以先把它备份到旁边的
+/// 日志文件里, 追加到一半进程崩溃的话下次打开时写回去. 覆盖已有章节时才在临时文件里
+/// 重写整个 zip 再 rename 过去, 已有的条目是原样复制的, 不用重新压缩
+#[derive(Debug)]
+pub struct ZipSink {
+    path: PathBuf,
+    /// 章节 id -> key
+    index: HashMap<ChapterId, ChapterKey>,
+}
+
+impl ZipSink {
+    pub const FILE_NAME: &str = "chapters.zip";
+
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        Self::recover(&path)?;
+        let mut index = HashMap::new();
+        if path.exists() {
+            let archive = zip::ZipArchive::new(File::open(&path)?)
+                .with_context(|| format!("打开 {path:?} 失败"))?;
+            for name in archive.file_names() {
+                if let Some((volume_dir, file_name)) = name.split_once('/')
+                    && let Some(key) = ChapterKey::parse_path(volume_dir, file_name)
+                {
+                    index.insert(key.id.clone(), key);
+                }
+            }
+        }
+        Ok(Self { path, index })
+    }
+
+    fn options() -> zip::write::SimpleFileOptions {
+        zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        self.path.with_extension("zip.tmp")
+    }
+
+    fn journal_path(path: &Path) -> PathBuf {
+        path.with_extension("zip.journal")
+    }
+
+    /// 上次追加没写完: 把备份的中央目录写回原来的位置, 追加了一半的数据丢掉
+    fn recover(path: &Path) -> anyhow::Result<()> {
+        let journal_path = Self::journal_path(path);
+        let journal = match std::fs::read(&journal_path) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let (offset, tail) = journal
+            .split_first_chunk::<8>()
+            .with_context(|| format!("{journal_path:?} 不完整"))?;
+        let offset = u64::from_le_bytes(*offset);
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(offset)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(tail)?;
+        file.sync_all()?;
+        std::fs::remove_file(&journal_path)?;
+        sync_parent(path)?;
+        warn!(?path, "上次存章节时中断了, 已经恢复");
+        Ok(())
+    }
+
+    /// 追加之前把中央目录 (从它开始到文件末尾) 和它的位置写进日志文件
+    fn write_journal(&self, file: &mut File) -> anyhow::Result<()> {
+        let offset = zip::ZipArchive::new(&mut *file)
+            .with_context(|| format!("打开 {:?} 失败", self.path))?
+            .central_directory_start();
+        let mut journal = offset.to_le_bytes().to_vec();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_to_end(&mut journal)?;
+        write_atomic(&Self::journal_path(&self.path), journal)
+    }
+
+    /// 在 zip 末尾追加一个新条目
+    fn append(&self, name: &str, html: &str) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.write_journal(&mut file)?;
+        let mut writer = zip::ZipWriter::new_append(file)?;
+        writer.start_file(name, Self::options())?;
+        writer.write_all(html.as_bytes())?;
+        writer.finish()?.sync_all()?;
+        std::fs::remove_file(Self::journal_path(&self.path))?;
+        sync_parent(&self.path)?;
+        Ok(())
+    }
+
+    /// 把原有条目 (去掉 `skip`) 和新条目写进临时文件, 原来的 zip 不动
+    fn write_tmp(&self, skip: Option<&str>, name: &str, html: &str) -> anyhow::Result<PathBuf> {
+        let tmp_path = self.tmp_path();
+        let mut writer = zip::ZipWriter::new(File::create(&tmp_path)?);
+        if self.path.exists() {
+            let mut archive = zip::ZipArchive::new(File::open(&self.path)?)?;
+            for i in 0..archive.len() {
+                let file = archive.by_index_raw(i)?;
+                if Some(file.name()) != skip {
+                    writer.raw_copy_file(file)?;
+                }
+            }
+        }
+        writer.start_file(name, Self::options())?;
+        writer.write_all(html.as_bytes())?;
+        writer.finish()?.sync_all()?;
+        Ok(tmp_path)
+    }
+}
+
+impl ChapterSink for ZipSink {
+    fn put(&mut self, key: &ChapterKey, html: &str) -> anyhow::Result<()> {
+        let name = key.relative_path();
+        match self.index.get(&key.id) {
+            None if self.path.exists() => self.append(&name, html)?,
+            old => {
+                let old = old.map(ChapterKey::relative_path);
+                let tmp_path = self.write_tmp(old.as_deref(), &name, html)?;
+                std::fs::rename(&tmp_path, &self.path)?;
+                sync_parent(&self.path)?;
+            }
+        }
+        self.index.insert(key.id.clone(), key.clone());
+        Ok(())
+    }
+
+    fn exists(&self, chapter_id: &ChapterId) -> anyhow::Result<bool> {
+        Ok(self.index.contains_key(chapter_id))
+    }
+
+    fn get(&self, chapter_id: &ChapterId) -> anyhow::Result<Option<String>> {
+        let Some(key) = self.index.get(chapter_id) else {
+            return Ok(None);
+        };
+        let mut archive = zip::ZipArchive::new(File::open(&self.path)?)?;
+        let mut html = String::new();
+        archive
+            .by_name(&key.relative_path())?
+            .read_to_string(&mut html)?;
+        Ok(Some(html))
+    }
+
+    fn list(&self) -> anyhow::Result<Vec<ChapterKey>> {
+        let mut keys: Vec<_> = self.index.values().cloned().collect();
+        keys.sort_by_key(ChapterKey::sort_key);
+        Ok(keys)
+    }
+}
+
+/// 整本书存成一个 sqlite 数据库
+#[derive(Debug)]
+pub struct SqliteSink {
+    conn: rusqlite::Connection,
+}
+
+impl SqliteSink {
+    pub const FILE_NAME: &str = "chapters.sqlite";
+
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn from_connection(conn: rusqlite::Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS chapters (
+                 id            TEXT PRIMARY KEY,
+                 volume_index  INTEGER NOT NULL,
+                 volume_title  TEXT NOT NULL,
+                 chapter_index INTEGER NOT NULL,
+                 title         TEXT NOT NULL,
+                 html          TEXT NOT NULL
+             );",
+        )?;
+        Ok(Self { conn })
+    }
+}
+
+impl ChapterSink for SqliteSink {
+    fn put(&mut self, key: &ChapterKey, html: &str) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO chapters
+                 (id, volume_index, volume_title, chapter_index, title, html)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                key.id,
+                key.volume_index as i64,
+                key.volume_title,
+                key.chapter_index as i64,
+                key.title,
+                html
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn exists(&self, chapter_id: &ChapterId) -> anyhow::Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT 1 FROM chapters WHERE id = ?1")?;
+        Ok(stmt.exists([chapter_id])?)
+    }
+
+    fn get(&self, chapter_id: &ChapterId) -> anyhow::Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT html FROM chapters WHERE id = ?1")?;
+        let mut rows = stmt.query([chapter_id])?;
+        Ok(match rows.next()? {
+            Some(row) => Some(row.get(0)?),
+            None => None,
+        })
+    }
+
+    fn list(&self) -> anyhow::Result<Vec<ChapterKey>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT volume_index, volume_title, chapter_index, title, id
+             FROM chapters ORDER BY volume_index, chapter_index",
+        )?;
+        let keys = stmt
+            .query_map([], |row| {
+                Ok(ChapterKey {
+                    volume_index: row.get::<_, i64>(0)? as usize,
+                    volume_title: row.get(1)?,
+                    chapter_index: row.get::<_, i64>(2)? as usize,
+                    title: row.get(3)?,
+                    id: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(volume_index: usize, chapter_index: usize, id: &str) -> ChapterKey {
+        ChapterKey {
+            volume_index,
+            volume_title: "正文卷".to_string(),
+            chapter_index,
+            title: format!("{chapter_index}.应杰"),
+            id: ChapterId::new(id),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("qidian-storage-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let k = key(0, 3, "748679604");
+        assert_eq!(k.relative_path(), "0_正文卷/3_3.应杰-748679604.html");
+        assert_eq!(
+            ChapterKey::parse_path(&k.volume_dir(), &k.file_name()),
+            Some(k)
+        );
+        // 章名里有 - 和 _ 也没关系
+        let parsed = ChapterKey::parse_path("2_2011", "10_第十章 a-b_c-763645826.html").unwrap();
+        assert_eq!(parsed.title, "第十章 a-b_c");
+        assert_eq!(parsed.id, "763645826");
+        assert_eq!(parsed.volume_index, 2);
+        assert_eq!(parsed.chapter_index, 10);
+
+        assert_eq!(ChapterKey::parse_path("正文卷", "1_a-1.html"), None);
+        assert_eq!(ChapterKey::parse_path("0_正文卷", "1_a-1.txt"), None);
+        assert_eq!(ChapterKey::parse_path("0_正文卷", "a-1.html"), None);
+        assert_eq!(ChapterKey::parse_path("0_正文卷", "1_a-b.html"), None);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize(" 上/下:篇? "), "上_下_篇_");
+    }
+
+    fn check_sink(sink: &mut dyn ChapterSink) {
+        assert!(sink.list().unwrap().is_empty());
+        sink.put(&key(1, 0, "3"), "<p>三</p>").unwrap();
+        sink.put(&key(0, 1, "2"), "<p>二</p>").unwrap();
+        sink.put(&key(0, 0, "1"), "<p>一</p>").unwrap();
+        assert!(sink.exists(&ChapterId::new("1")).unwrap());
+        assert!(!sink.exists(&ChapterId::new("4")).unwrap());
+        assert_eq!(
+            sink.get(&ChapterId::new("2")).unwrap().as_deref(),
+            Some("<p>二</p>")
+        );
+        assert_eq!(sink.get(&ChapterId::new("4")).unwrap(), None);
+
+        // 覆盖
+        sink.put(&key(0, 1, "2"), "<p>二改</p>").unwrap();
+        assert_eq!(
+            sink.get(&ChapterId::new("2")).unwrap().as_deref(),
+            Some("<p>二改</p>")
+        );
+
+        let ids: Vec<_> = sink.list().unwrap().into_iter().map(|k| k.id).collect();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_dir_sink() {
+        let dir = temp_dir("dir");
+        check_sink(&mut DirSink::open(&dir).unwrap());
+        // 重新打开能扫描出已有的章节
+        assert_eq!(DirSink::open(&dir).unwrap().list().unwrap().len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip_sink() {
+        let dir = temp_dir("zip");
+        let path = dir.join(ZipSink::FILE_NAME);
+        check_sink(&mut ZipSink::open(&path).unwrap());
+        let reopened = ZipSink::open(&path).unwrap();
+        assert_eq!(reopened.list().unwrap().len(), 3);
+        assert_eq!(
+            reopened.get(&ChapterId::new("2")).unwrap().as_deref(),
+            Some("<p>二改</p>")
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip_sink_interrupted_put() {
+        let dir = temp_dir("zip-interrupted");
+        let path = dir.join(ZipSink::FILE_NAME);
+        let mut sink = ZipSink::open(&path).unwrap();
+        sink.put(&key(0, 0, "1"), "<p>一</p>").unwrap();
+
+        // 写临时文件的时候被打断: 只写了一半, 还没 rename
+        let tmp_path = sink
+            .write_tmp(None, &key(0, 1, "2").relative_path(), "<p>二</p>")
+            .unwrap();
+        let len = std::fs::metadata(&tmp_path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&tmp_path)
+            .unwrap()
+            .set_len(len / 2)
+            .unwrap();
+
+        let mut reopened = ZipSink::open(&path).unwrap();
+        assert_eq!(reopened.list().unwrap().len(), 1);
+        assert_eq!(
+            reopened.get(&ChapterId::new("1")).unwrap().as_deref(),
+            Some("<p>一</p>")
+        );
+        // 下次存的时候残留的临时文件直接被覆盖
+        reopened.put(&key(0, 1, "2"), "<p>二</p>").unwrap();
+        assert_eq!(ZipSink::open(&path).unwrap().list().unwrap().len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip_sink_interrupted_append() {
+        let dir = temp_dir("zip-append");
+        let path = dir.join(ZipSink::FILE_NAME);
+        let mut sink = ZipSink::open(&path).unwrap();
+        sink.put(&key(0, 0, "1"), "<p>一</p>").unwrap();
+        sink.put(&key(0, 1, "2"), "<p>二</p>").unwrap();
+        assert!(!ZipSink::journal_path(&path).exists());
+
+        // 追加的时候被打断: 日志写好了, 中央目录被写了一半的新条目盖掉
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        sink.write_journal(&mut file).unwrap();
+        let offset = zip::ZipArchive::new(&mut file)
+            .unwrap()
+            .central_directory_start();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[0x50, 0x4b, 0x03, 0x04, 0, 0, 0]).unwrap();
+        drop(file);
+        assert!(zip::ZipArchive::new(File::open(&path).unwrap()).is_err());
+
+        let mut reopened = ZipSink::open(&path).unwrap();
+        assert!(!ZipSink::journal_path(&path).exists());
+        assert_eq!(reopened.list().unwrap().len(), 2);
+        assert_eq!(
+            reopened.get(&ChapterId::new("2")).unwrap().as_deref(),
+            Some("<p>二</p>")
+        );
+        reopened.put(&key(0, 2, "3"), "<p>三</p>").unwrap();
+        assert_eq!(ZipSink::open(&path).unwrap().list().unwrap().len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sqlite_sink() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        check_sink(&mut SqliteSink::from_connection(conn).unwrap());
+    }
+}
@@ -0,0 +1,374 @@
+//! 检查下载好的章节
+//!
+//! 按目录里的字数核对存下来的正文, 找出空的, 只有一半的, 和上一章重复的章节
+
+use std::{fmt, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    books::{BookChapter, BookInfo},
+    ids::{BookId, ChapterId},
+    library,
+    manifest::Manifest,
+    parse_page::{chapter_content, reader_page},
+    revisions,
+    storage::{self, ChapterSink},
+};
+
+/// 检查没通过时的退出码
+pub const EXIT_VERIFY_FAILED: i32 = 2;
+
+/// 有章节没通过检查
+#[derive(Debug, Clone, Copy)]
+pub struct VerifyFailed {
+    pub failures: usize,
+}
+
+impl fmt::Display for VerifyFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 章没有通过检查", self.failures)
+    }
+}
+
+impl std::error::Error for VerifyFailed {}
+
+/// 检查的参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerifyConfig {
+    /// 实际字数和目录字数差多少比例以内算正常
+    pub tolerance: f64,
+    /// 少于这么多字算太短 (目录里的字数也这么少的除外)
+    pub min_chars: usize,
+}
+
+/// 解析命令行里的 `--tolerance`, 百分比, 只能在 0 到 100 之间
+pub fn parse_tolerance(s: &str) -> Result<f64, String> {
+    let percent: f64 = s.trim().parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("{percent} 不在 0 到 100 之间"));
+    }
+    Ok(percent)
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.15,
+            min_chars: 200,
+        }
+    }
+}
+
+/// 一章的问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Issue {
+    /// 目录里有, 存储里没有
+    Missing,
+    /// 解析不出正文
+    Empty,
+    /// 付费提示页, 或者比目录字数少得多
+    Truncated,
+    /// 和上一章内容一样
+    Duplicate,
+    /// 字数太少
+    Short,
+    /// 比目录字数多得多
+    Long,
+}
+
+impl Issue {
+    /// 算不算失败, 失败的会让 `verify` 返回非 0
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Issue::Short | Issue::Long)
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Issue::Missing => "缺失",
+            Issue::Empty => "空白",
+            Issue::Truncated => "不完整",
+            Issue::Duplicate => "和上一章重复",
+            Issue::Short => "太短",
+            Issue::Long => "太长",
+        })
+    }
+}
+
+/// 一章的检查结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChapterCheck {
+    pub chapter_id: ChapterId,
+    pub title: String,
+    /// 目录里的字数
+    pub expected: u32,
+    /// 正文实际字数, 没存就是 0
+    pub actual: usize,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    pub book_id: BookId,
+    pub checked: usize,
+    /// 没订阅的章节不检查
+    pub locked: usize,
+    /// 只有有问题的章节
+    pub chapters: Vec<ChapterCheck>,
+}
+
+impl VerifyReport {
+    pub fn failures(&self) -> usize {
+        self.chapters
+            .iter()
+            .filter(|check| check.issues.iter().any(Issue::is_failure))
+            .count()
+    }
+}
+
+fn check_chapter(
+    chapter: &BookChapter,
+    html: Option<&str>,
+    previous_hash: Option<&str>,
+    config: &VerifyConfig,
+) -> (ChapterCheck, Option<String>) {
+    let mut check = ChapterCheck {
+        chapter_id: chapter.id.clone(),
+        title: chapter.title.clone(),
+        expected: chapter.length,
+        actual: 0,
+        issues: Vec::new(),
+    };
+    let Some(html) = html else {
+        check.issues.push(Issue::Missing);
+        return (check, None);
+    };
+    check.actual = chapter_content::parse(html).char_count();
+    let hash = revisions::content_hash(html);
+    let expected = chapter.length as f64;
+    let actual = check.actual as f64;
+
+    if check.actual == 0 {
+        check.issues.push(Issue::Empty);
+        return (check, Some(hash));
+    }
+    if reader_page::is_truncated(html, chapter.length)
+        || (chapter.length > 0 && actual < expected * (1.0 - config.tolerance))
+    {
+        check.issues.push(Issue::Truncated);
+    } else if chapter.length > 0 && actual > expected * (1.0 + config.tolerance) {
+        check.issues.push(Issue::Long);
+    }
+    if previous_hash == Some(hash.as_str()) {
+        check.issues.push(Issue::Duplicate);
+    }
+    if check.actual < config.min_chars
+        && (chapter.length as usize) >= config.min_chars
+        && !check.issues.contains(&Issue::Truncated)
+    {
+        check.issues.push(Issue::Short);
+    }
+    (check, Some(hash))
+}
+
+/// 按目录顺序检查一本书, 重复只和同一卷里的上一章比
+pub fn verify_book(
+    book: &BookInfo,
+    sink: &dyn ChapterSink,
+    config: &VerifyConfig,
+) -> anyhow::Result<VerifyReport> {
+    let mut report = VerifyReport {
+        book_id: book.id.clone(),
+        ..Default::default()
+    };
+    for volume in &book.volumes {
+        let mut previous_hash = None;
+        for chapter in &volume.chapters {
+            if chapter.is_locked() {
+                report.locked += 1;
+                continue;
+            }
+            let html = sink.get(&chapter.id)?;
+            let (check, hash) =
+                check_chapter(chapter, html.as_deref(), previous_hash.as_deref(), config);
+            report.checked += 1;
+            if !check.issues.is_empty() {
+                report.chapters.push(check);
+            }
+            previous_hash = hash;
+        }
+    }
+    Ok(report)
+}
+
+fn print_table(report: &VerifyReport) {
+    println!(
+        "检查了 {} 章, {} 章有问题, {} 章没订阅没检查",
+        report.checked,
+        report.chapters.len(),
+        report.locked
+    );
+    if report.chapters.is_empty() {
+        return;
+    }
+    println!(
+        "{:<12} {:>8} {:>8}  问题  标题",
+        "章节 id", "目录字数", "实际字数"
+    );
+    for check in &report.chapters {
+        let issues: Vec<_> = check.issues.iter().map(Issue::to_string).collect();
+        println!(
+            "{:<12} {:>8} {:>8}  {}  {}",
+            check.chapter_id,
+            check.expected,
+            check.actual,
+            issues.join(", "),
+            check.title
+        );
+    }
+}
+
+/// `verify` 子命令, 有失败的章节时返回 [`VerifyFailed`]
+pub fn run(dir: &str, config: &VerifyConfig, json: bool) -> anyhow::Result<()> {
+    let dir = Path::new(dir);
+    let sink = storage::open_existing(dir)?;
+    let mut book = library::load_catalog(dir, sink.as_ref())?;
+    let manifest_path = dir.join(Manifest::FILE_NAME);
+    if manifest_path.exists() {
+        Manifest::load_for(&manifest_path, &book.id)?.apply_locked(&mut book);
+    }
+    let report = verify_book(&book, sink.as_ref(), config)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_table(&report);
+    }
+    match report.failures() {
+        0 => Ok(()),
+        failures => Err(VerifyFailed { failures }.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        books::BookVolume,
+        ids::VolumeId,
+        storage::{ChapterKey, SqliteSink},
+    };
+
+    fn paragraphs(n: usize, text: &str) -> String {
+        format!("<h1>标题</h1><p>{}</p>", text.repeat(n))
+    }
+
+    #[test]
+    fn test_parse_tolerance() {
+        assert_eq!(parse_tolerance("15"), Ok(15.0));
+        assert_eq!(parse_tolerance("0"), Ok(0.0));
+        assert_eq!(parse_tolerance("100"), Ok(100.0));
+        for bad in ["-1", "100.5", "NaN", "inf", "abc"] {
+            assert!(parse_tolerance(bad).is_err(), "{bad}");
+        }
+        let parse = |tolerance: &str| {
+            <crate::CliArg as clap::Parser>::try_parse_from([
+                "qidian-downloader",
+                "verify",
+                "out/1",
+                "--tolerance",
+                tolerance,
+            ])
+        };
+        assert!(parse("20").is_ok());
+        assert!(parse("150").is_err());
+    }
+
+    #[test]
+    fn test_verify_book() {
+        let chapter = |id: &str, length: u32| {
+            BookChapter::new(
+                format!("{id}.章"),
+                length,
+                None,
+                ChapterId::new(id),
+                String::new(),
+            )
+        };
+        let book = BookInfo {
+            id: BookId::new("1036741406"),
+            title: String::new(),
+            volumes: vec![BookVolume {
+                title: "正文卷".to_string(),
+                is_vip: false,
+                id: VolumeId::new("vol1"),
+                chapters: vec![
+                    chapter("1", 1000),
+                    chapter("2", 1000),
+                    chapter("3", 1000),
+                    chapter("4", 1000),
+                    chapter("5", 1000),
+                    chapter("6", 1000),
+                    chapter("7", 100),
+                ],
+            }],
+        };
+        let mut sink =
+            SqliteSink::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let volume = &book.volumes[0];
+        let contents = [
+            paragraphs(200, "正常的内容"),
+            // 和上一章一样
+            paragraphs(200, "正常的内容"),
+            paragraphs(10, "少了一大半"),
+            "<h1>标题</h1>".to_string(),
+            paragraphs(60, "一半的内容"),
+            // 6 没存
+        ];
+        for (i, html) in contents.iter().enumerate() {
+            sink.put(&ChapterKey::new(0, volume, i, &volume.chapters[i]), html)
+                .unwrap();
+        }
+        sink.put(
+            &ChapterKey::new(0, volume, 6, &volume.chapters[6]),
+            &paragraphs(50, "短章"),
+        )
+        .unwrap();
+
+        let report = verify_book(&book, &sink, &VerifyConfig::default()).unwrap();
+        assert_eq!(report.checked, 7);
+        let issues: Vec<_> = report
+            .chapters
+            .iter()
+            .map(|check| (check.chapter_id.as_str(), check.issues.clone()))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("2", vec![Issue::Duplicate]),
+                ("3", vec![Issue::Truncated]),
+                ("4", vec![Issue::Empty]),
+                ("5", vec![Issue::Truncated]),
+                ("6", vec![Issue::Missing]),
+            ]
+        );
+        assert_eq!(report.failures(), 5);
+        assert_eq!(report.chapters[1].actual, 50);
+
+        // 放宽到差 75% 以内, 一半的内容 (300 字) 只算太短
+        let loose = VerifyConfig {
+            tolerance: 0.75,
+            min_chars: 400,
+        };
+        let report = verify_book(&book, &sink, &loose).unwrap();
+        let five = report
+            .chapters
+            .iter()
+            .find(|check| check.chapter_id == "5")
+            .unwrap();
+        assert_eq!(five.issues, vec![Issue::Short]);
+        assert!(!five.issues[0].is_failure());
+    }
+}
@@ -0,0 +1,279 @@
+//! 简繁转换
+//!
+//! 词典在 `src/dict` 下面, 编译时打包进程序, 不用联网. 先按词 (最长匹配) 再按字转换,
+//! 这样 "头发" 和 "发展" 里的 "发" 能转成不同的字
+
+use std::{collections::HashMap, str::FromStr};
+
+use crate::{books::BookInfo, parse_page::chapter_content::ChapterContent};
+
+const ST_CHARACTERS: &str = include_str!("dict/STCharacters.txt");
+const ST_PHRASES: &str = include_str!("dict/STPhrases.txt");
+const TS_CHARACTERS: &str = include_str!("dict/TSCharacters.txt");
+const TS_PHRASES: &str = include_str!("dict/TSPhrases.txt");
+const TW_VARIANTS: &str = include_str!("dict/TWVariants.txt");
+const TW_VARIANTS_REV_PHRASES: &str = include_str!("dict/TWVariantsRevPhrases.txt");
+const TW_PHRASES: &str = include_str!("dict/TWPhrases.txt");
+const HK_VARIANTS: &str = include_str!("dict/HKVariants.txt");
+
+/// 转换方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    /// 简体到繁体
+    S2T,
+    /// 简体到台湾正体, 包括台湾用语
+    S2TW,
+    /// 简体到香港繁体
+    S2HK,
+    /// 繁体到简体
+    T2S,
+    /// 台湾正体到简体
+    TW2S,
+    /// 香港繁体到简体
+    HK2S,
+}
+
+impl FromStr for Conversion {
+    type Err = std::io::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "s2t" => Ok(Conversion::S2T),
+            "s2tw" => Ok(Conversion::S2TW),
+            "s2hk" => Ok(Conversion::S2HK),
+            "t2s" => Ok(Conversion::T2S),
+            "tw2s" => Ok(Conversion::TW2S),
+            "hk2s" => Ok(Conversion::HK2S),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid conversion",
+            )),
+        }
+    }
+}
+
+impl Conversion {
+    /// html 的 `lang`
+    pub fn lang(&self) -> &'static str {
+        match self {
+            Conversion::S2T => "zh-Hant",
+            Conversion::S2TW => "zh-TW",
+            Conversion::S2HK => "zh-HK",
+            Conversion::T2S | Conversion::TW2S | Conversion::HK2S => "zh-Hans",
+        }
+    }
+}
+
+/// 一张词典, 字和词放在一起, 转换时取最长的匹配
+#[derive(Debug, Clone, Default)]
+struct Dictionary {
+    map: HashMap<String, String>,
+    /// 最长的词有几个字
+    max_len: usize,
+}
+
+impl Dictionary {
+    /// 每行 `原文\t译文`, `#` 开头的是注释
+    fn parse(data: &str) -> impl Iterator<Item = (&str, &str)> {
+        data.lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('\t'))
+    }
+
+    fn insert(&mut self, from: &str, to: &str) {
+        self.max_len = self.max_len.max(from.chars().count());
+        self.map
+            .entry(from.to_string())
+            .or_insert_with(|| to.to_string());
+    }
+
+    /// 前面的词典优先
+    fn new(dicts: &[&str]) -> Self {
+        let mut dictionary = Self::default();
+        for data in dicts {
+            for (from, to) in Self::parse(data) {
+                dictionary.insert(from, to);
+            }
+        }
+        dictionary
+    }
+
+    /// 反过来用的词典, `reversed` 里的词典会把每行的两边对调
+    fn with_reversed(dicts: &[&str], reversed: &[&str]) -> Self {
+        let mut dictionary = Self::new(dicts);
+        for data in reversed {
+            for (to, from) in Self::parse(data) {
+                dictionary.insert(from, to);
+            }
+        }
+        dictionary
+    }
+
+    fn convert(&self, text: &str) -> String {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let mut out = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let start = chars[i].0;
+            let matched = (1..=self.max_len.min(chars.len() - i))
+                .rev()
+                .find_map(|len| {
+                    let end = chars.get(i + len).map_or(text.len(), |(end, _)| *end);
+                    self.map.get(&text[start..end]).map(|to| (len, to))
+                });
+            match matched {
+                Some((len, to)) => {
+                    out.push_str(to);
+                    i += len;
+                }
+                None => {
+                    out.push(chars[i].1);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+}
+
+/// 简繁转换器, 按顺序过几张词典
+#[derive(Debug, Clone)]
+pub struct Converter {
+    conversion: Conversion,
+    steps: Vec<Dictionary>,
+}
+
+impl Converter {
+    pub fn new(conversion: Conversion) -> Self {
+        let s2t = || Dictionary::new(&[ST_PHRASES, ST_CHARACTERS]);
+        let t2s = || {
+            Dictionary::with_reversed(&[TS_PHRASES, TS_CHARACTERS], &[ST_PHRASES, ST_CHARACTERS])
+        };
+        let steps = match conversion {
+            Conversion::S2T => vec![s2t()],
+            Conversion::S2TW => vec![
+                s2t(),
+                Dictionary::new(&[TW_PHRASES]),
+                Dictionary::new(&[TW_VARIANTS]),
+            ],
+            Conversion::S2HK => vec![s2t(), Dictionary::new(&[HK_VARIANTS])],
+            Conversion::T2S => vec![t2s()],
+            Conversion::TW2S => vec![
+                Dictionary::with_reversed(&[TW_VARIANTS_REV_PHRASES], &[TW_VARIANTS]),
+                Dictionary::with_reversed(&[], &[TW_PHRASES]),
+                t2s(),
+            ],
+            Conversion::HK2S => vec![Dictionary::with_reversed(&[], &[HK_VARIANTS]), t2s()],
+        };
+        Self { conversion, steps }
+    }
+
+    pub fn conversion(&self) -> Conversion {
+        self.conversion
+    }
+
+    pub fn convert(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for step in &self.steps {
+            text = step.convert(&text);
+        }
+        text
+    }
+
+    /// 转换书名, 卷名和章节标题
+    pub fn convert_book(&self, book: &BookInfo) -> BookInfo {
+        let mut book = book.clone();
+        book.title = self.convert(&book.title);
+        for volume in &mut book.volumes {
+            volume.title = self.convert(&volume.title);
+            for chapter in &mut volume.chapters {
+                chapter.title = self.convert(&chapter.title);
+            }
+        }
+        book
+    }
+
+    /// 转换解析出来的正文: 标题, 段落和附带部分. 插图的地址和说明不动
+    pub fn convert_content(&self, content: &mut ChapterContent) {
+        if let Some(title) = &mut content.title {
+            *title = self.convert(title);
+        }
+        for paragraph in &mut content.paragraphs {
+            *paragraph = self.convert(paragraph);
+        }
+        for section in &mut content.sections {
+            for paragraph in &mut section.paragraphs {
+                *paragraph = self.convert(paragraph);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let s2t = Converter::new(Conversion::S2T);
+        // 同一个字按词转成不同的写法
+        assert_eq!(s2t.convert("头发的发展"), "頭髮的發展");
+        assert_eq!(s2t.convert("干净的干部"), "乾淨的幹部");
+        assert_eq!(s2t.convert("这里有三公里"), "這裏有三公里");
+        assert_eq!(s2t.convert("Chapter 1: 围棋"), "Chapter 1: 圍棋");
+
+        let s2tw = Converter::new(Conversion::S2TW);
+        assert_eq!(s2tw.convert("这里的软件看着不错"), "這裡的軟體看著不錯");
+        let s2hk = Converter::new(Conversion::S2HK);
+        assert_eq!(s2hk.convert("他说的线"), "他説的綫");
+
+        let t2s = Converter::new(Conversion::T2S);
+        assert_eq!(t2s.convert("頭髮乾淨, 乾坤未定"), "头发干净, 乾坤未定");
+        assert_eq!(t2s.convert("這裡"), "这里");
+        let tw2s = Converter::new(Conversion::TW2S);
+        assert_eq!(
+            tw2s.convert("他看著窗外, 讀名著, 裝軟體"),
+            "他看着窗外, 读名著, 装软件"
+        );
+        let hk2s = Converter::new(Conversion::HK2S);
+        assert_eq!(hk2s.convert("他説的綫"), "他说的线");
+
+        assert_eq!(s2t.convert("于是他走到里面"), "於是他走到裏面");
+        assert_eq!(s2t.convert("后来出发时才发现"), "後來出發時才發現");
+        assert_eq!(s2tw.convert("于是他走到里面"), "於是他走到裡面");
+        assert_eq!(t2s.convert("於是他走到裏面, 出發"), "于是他走到里面, 出发");
+    }
+
+    #[test]
+    fn test_convert_chapter() {
+        // 一章普通的小说正文, 转过去再转回来要一样
+        let simplified = include_str!("dict/test_simplified.txt");
+        let traditional = include_str!("dict/test_traditional.txt");
+        let s2t = Converter::new(Conversion::S2T);
+        let t2s = Converter::new(Conversion::T2S);
+        for (s, t) in simplified.lines().zip(traditional.lines()) {
+            assert_eq!(s2t.convert(s), t);
+            assert_eq!(t2s.convert(t), s);
+        }
+        assert_eq!(simplified.lines().count(), traditional.lines().count());
+    }
+
+    #[test]
+    fn test_dictionaries() {
+        // 每行都得是 `原文\t译文`
+        for data in [
+            ST_CHARACTERS,
+            ST_PHRASES,
+            TS_CHARACTERS,
+            TS_PHRASES,
+            TW_VARIANTS,
+            TW_VARIANTS_REV_PHRASES,
+            TW_PHRASES,
+            HK_VARIANTS,
+        ] {
+            for line in data.lines().filter(|line| !line.starts_with('#')) {
+                let (from, to) = line.split_once('\t').unwrap();
+                assert!(!from.is_empty() && !to.is_empty(), "{line}");
+            }
+        }
+    }
+}